@@ -16,12 +16,14 @@ use embedded_alloc::LlffHeap as Heap;
 #[global_allocator]
 static HEAP: Heap = Heap::empty();
 
+const HEAP_SIZE: usize = 16;
+
 #[entry]
 fn main() -> ! {
-    const HEAP_SIZE: usize = 16;
+    // `#[entry]` turns this into a `&'static mut`, so the heap can borrow it safely.
     static mut HEAP_MEM: [MaybeUninit<u8>; HEAP_SIZE] = [MaybeUninit::uninit(); HEAP_SIZE];
     let heap: Heap = Heap::empty();
-    unsafe { heap.init(&raw mut HEAP_MEM as usize, HEAP_SIZE) }
+    heap.init_with(HEAP_MEM);
 
     let mut vec = alloc::vec::Vec::new_in(heap);
     vec.push(1);
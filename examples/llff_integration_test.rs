@@ -2,14 +2,17 @@
 //! Reference the QEMU section of the [Embedded Rust Book] for more information
 //!
 //! This only tests integration of the allocator on an embedded target.
-//! Comprehensive allocator tests are located in the allocator dependency.
+//! Comprehensive allocator tests run on the host, see the `tests` directory.
 //!
 //! After toolchain installation this test can be run with:
 //!
 //! ```bash
-//! cargo +nightly run --target thumbv7m-none-eabi --example llff_integration_test --all-features
+//! cargo +nightly run --target thumbv7m-none-eabi --example llff_integration_test --features allocator_api,stats,poison
 //! ```
 //!
+//! The sizes checked here don't account for the canaries of `guard` or the size classes
+//! of `isr_cache`, so those features are left out.
+//!
 //! [Embedded Rust Book]: https://docs.rust-embedded.org/book/intro/index.html
 
 #![feature(allocator_api)]
@@ -26,7 +29,7 @@ use core::{
 use cortex_m as _;
 use cortex_m_rt::entry;
 use defmt_semihosting as _;
-use embedded_alloc::LlffHeap as Heap;
+use embedded_alloc::{LlffHeap as Heap, LocalLlffHeap};
 
 #[global_allocator]
 static HEAP: Heap = Heap::empty();
@@ -49,12 +52,11 @@ fn test_allocator_api() {
     // small local heap
     const HEAP_SIZE: usize = 16;
     let mut heap_mem: [MaybeUninit<u8>; HEAP_SIZE] = [MaybeUninit::uninit(); HEAP_SIZE];
-    let local_heap: Heap = Heap::empty();
-    unsafe { local_heap.init(&raw mut heap_mem as usize, HEAP_SIZE) }
+    let local_heap = LocalLlffHeap::new(&mut heap_mem);
 
-    assert_eq!(local_heap.used(), 0);
+    assert_eq!(local_heap.heap().used(), 0);
 
-    let mut v: Vec<u16, Heap> = Vec::new_in(local_heap);
+    let mut v: Vec<u16, &LocalLlffHeap> = Vec::new_in(&local_heap);
     v.push(0xCAFE);
     v.extend(&[0xDEAD, 0xFEED]);
 
@@ -2,14 +2,17 @@
 //! Reference the QEMU section of the [Embedded Rust Book] for more information
 //!
 //! This only tests integration of the allocator on an embedded target.
-//! Comprehensive allocator tests are located in the allocator dependency.
+//! Comprehensive allocator tests run on the host, see the `tests` directory.
 //!
 //! After toolchain installation this test can be run with:
 //!
 //! ```bash
-//! cargo +nightly run --target thumbv7m-none-eabi --example tlsf_integration_test --all-features
+//! cargo +nightly run --target thumbv7m-none-eabi --example tlsf_integration_test --features allocator_api,stats,poison
 //! ```
 //!
+//! The sizes checked here don't account for the canaries of `guard` or the size classes
+//! of `isr_cache`, so those features are left out.
+//!
 //! [Embedded Rust Book]: https://docs.rust-embedded.org/book/intro/index.html
 
 #![feature(allocator_api)]
@@ -23,7 +26,7 @@ use alloc::collections::LinkedList;
 use core::{mem::MaybeUninit, panic::PanicInfo};
 use cortex_m as _;
 use cortex_m_rt::entry;
-use embedded_alloc::TlsfHeap as Heap;
+use embedded_alloc::{LocalTlsfHeap, TlsfHeap as Heap};
 
 #[global_allocator]
 static HEAP: Heap = Heap::empty();
@@ -61,12 +64,11 @@ fn test_allocator_api() {
     // small local heap
     const HEAP_SIZE: usize = 256;
     let mut heap_mem: [MaybeUninit<u8>; HEAP_SIZE] = [MaybeUninit::uninit(); HEAP_SIZE];
-    let local_heap: Heap = Heap::empty();
-    unsafe { local_heap.init(heap_mem.as_mut_ptr() as usize, HEAP_SIZE) }
+    let local_heap = LocalTlsfHeap::new(&mut heap_mem);
 
     const ELEMS: usize = 2;
 
-    let mut allocated = LinkedList::new_in(local_heap);
+    let mut allocated = LinkedList::new_in(&local_heap);
     for _ in 0..ELEMS {
         allocated.push_back(0);
     }
@@ -15,12 +15,14 @@ use embedded_alloc::TlsfHeap as Heap;
 #[global_allocator]
 static HEAP: Heap = Heap::empty();
 
+const HEAP_SIZE: usize = 4096;
+
 #[entry]
 fn main() -> ! {
-    // Initialize the allocator BEFORE you use it
-    const HEAP_SIZE: usize = 4096;
+    // `#[entry]` turns this into a `&'static mut`, so the heap can borrow it safely.
     static mut HEAP_MEM: [MaybeUninit<u8>; HEAP_SIZE] = [MaybeUninit::uninit(); HEAP_SIZE];
-    unsafe { HEAP.init(&raw mut HEAP_MEM as usize, HEAP_SIZE) }
+    // Initialize the allocator BEFORE you use it
+    HEAP.init_with(HEAP_MEM);
 
     let mut alloc_vecs = alloc::vec::Vec::new();
     let mut free_memory = HEAP_SIZE;
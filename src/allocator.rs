@@ -0,0 +1,65 @@
+use core::alloc::{AllocError, Allocator, Layout};
+use core::ptr::{self, NonNull};
+
+/// Moves an allocation from `old_layout` to `new_layout`, in place if possible.
+///
+/// `realloc` resizes an allocation of a heap keeping its alignment, like
+/// `GlobalAlloc::realloc`. A block can only be resized in place if its alignment stays
+/// the same, so other allocations are moved through `allocator`.
+///
+/// Zero-sized allocations are dangling pointers that were never handed out by the heap,
+/// so resizing from or to zero is an allocation or deallocation instead.
+///
+/// # Safety
+///
+/// The requirements of `Allocator::grow` or `Allocator::shrink` apply.
+pub(crate) unsafe fn resize(
+    allocator: &impl Allocator,
+    ptr: NonNull<u8>,
+    old_layout: Layout,
+    new_layout: Layout,
+    realloc: impl FnOnce(NonNull<u8>, Layout, usize) -> Option<NonNull<u8>>,
+) -> Result<NonNull<[u8]>, AllocError> {
+    if old_layout.size() == 0 {
+        return allocator.allocate(new_layout);
+    }
+    if new_layout.size() == 0 {
+        allocator.deallocate(ptr, old_layout);
+        return Ok(NonNull::slice_from_raw_parts(new_layout.dangling_ptr(), 0));
+    }
+    if old_layout.align() == new_layout.align() {
+        return realloc(ptr, old_layout, new_layout.size()).map_or(Err(AllocError), |allocation| {
+            Ok(NonNull::slice_from_raw_parts(allocation, new_layout.size()))
+        });
+    }
+
+    let allocation = allocator.allocate(new_layout)?;
+    ptr::copy_nonoverlapping(
+        ptr.as_ptr(),
+        allocation.cast::<u8>().as_ptr(),
+        old_layout.size().min(new_layout.size()),
+    );
+    allocator.deallocate(ptr, old_layout);
+    Ok(allocation)
+}
+
+/// Like [`resize`], but zeroes the memory the allocation grew by.
+///
+/// # Safety
+///
+/// The requirements of `Allocator::grow_zeroed` apply.
+pub(crate) unsafe fn resize_zeroed(
+    allocator: &impl Allocator,
+    ptr: NonNull<u8>,
+    old_layout: Layout,
+    new_layout: Layout,
+    realloc: impl FnOnce(NonNull<u8>, Layout, usize) -> Option<NonNull<u8>>,
+) -> Result<NonNull<[u8]>, AllocError> {
+    let allocation = resize(allocator, ptr, old_layout, new_layout, realloc)?;
+    allocation
+        .cast::<u8>()
+        .as_ptr()
+        .add(old_layout.size())
+        .write_bytes(0, new_layout.size() - old_layout.size());
+    Ok(allocation)
+}
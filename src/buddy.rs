@@ -0,0 +1,398 @@
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::RefCell;
+use core::mem::size_of;
+use core::ptr::{self, NonNull};
+
+use critical_section::Mutex;
+
+use crate::InitError;
+
+/// Header stored at the start of every free block.
+struct FreeBlock {
+    next: Option<NonNull<FreeBlock>>,
+    prev: Option<NonNull<FreeBlock>>,
+    order: u32,
+}
+
+const ORDERS: usize = usize::BITS as usize;
+
+struct Inner {
+    /// One list of free blocks for every order, that is every block size `1 << order`.
+    free_lists: [Option<NonNull<FreeBlock>>; ORDERS],
+    /// One bit for every block of the minimum size, set if a free block starts there.
+    bitmap: *mut u8,
+    base: usize,
+    end: usize,
+    used: usize,
+    initialized: bool,
+}
+
+impl Inner {
+    fn bit(&self, addr: usize, min_order: u32) -> (*mut u8, u8) {
+        let index = (addr - self.base) >> min_order;
+        // Safety: The bitmap covers every block of the heap.
+        (unsafe { self.bitmap.add(index / 8) }, 1 << (index % 8))
+    }
+
+    fn is_free(&self, addr: usize, min_order: u32) -> bool {
+        let (byte, mask) = self.bit(addr, min_order);
+        // Safety: `bit` returns a byte of the bitmap.
+        unsafe { *byte & mask != 0 }
+    }
+
+    unsafe fn set_free(&mut self, addr: usize, min_order: u32, free: bool) {
+        let (byte, mask) = self.bit(addr, min_order);
+        if free {
+            *byte |= mask;
+        } else {
+            *byte &= !mask;
+        }
+    }
+
+    /// Adds the block at `addr` to the free list of `order`.
+    unsafe fn push(&mut self, addr: usize, order: u32, min_order: u32) {
+        let block = addr as *mut FreeBlock;
+        let next = self.free_lists[order as usize];
+        block.write(FreeBlock {
+            next,
+            prev: None,
+            order,
+        });
+        let block = NonNull::new_unchecked(block);
+        if let Some(next) = next {
+            (*next.as_ptr()).prev = Some(block);
+        }
+        self.free_lists[order as usize] = Some(block);
+        self.set_free(addr, min_order, true);
+    }
+
+    /// Removes the free block `block` from its free list.
+    unsafe fn remove(&mut self, block: NonNull<FreeBlock>, min_order: u32) {
+        let FreeBlock { next, prev, order } = block.as_ptr().read();
+        match prev {
+            Some(prev) => (*prev.as_ptr()).next = next,
+            None => self.free_lists[order as usize] = next,
+        }
+        if let Some(next) = next {
+            (*next.as_ptr()).prev = prev;
+        }
+        self.set_free(block.as_ptr() as usize, min_order, false);
+    }
+
+    /// Frees the block at `addr`, merging it with its buddies.
+    unsafe fn free_block(&mut self, mut addr: usize, mut order: u32, min_order: u32) {
+        while (order as usize) < ORDERS - 1 {
+            let buddy = addr ^ (1 << order);
+            let in_heap = buddy >= self.base && buddy < self.end;
+            if !in_heap || !self.is_free(buddy, min_order) {
+                break;
+            }
+            let buddy = NonNull::new_unchecked(buddy as *mut FreeBlock);
+            if buddy.as_ref().order != order {
+                break;
+            }
+            self.remove(buddy, min_order);
+            addr = addr.min(buddy.as_ptr() as usize);
+            order += 1;
+        }
+        self.push(addr, order, min_order);
+    }
+}
+
+// Safety: The whole inner type is wrapped by a [Mutex].
+unsafe impl Sync for Inner {}
+unsafe impl Send for Inner {}
+
+/// A binary buddy heap.
+///
+/// Every allocation is rounded up to a power of two, at least `1 << MIN_ORDER` bytes,
+/// and placed at an address aligned to its size. This suits the region rules of the
+/// Cortex-M MPU and DMA engines with alignment requirements.
+///
+/// `1 << MIN_ORDER` must be at least `3 * size_of::<usize>()` bytes, the size of the
+/// header of a free block.
+pub struct Heap<const MIN_ORDER: u32 = 5> {
+    heap: Mutex<RefCell<Inner>>,
+}
+
+impl<const MIN_ORDER: u32> Heap<MIN_ORDER> {
+    const MIN_SIZE: usize = {
+        assert!(
+            MIN_ORDER < usize::BITS && 1 << MIN_ORDER >= size_of::<FreeBlock>(),
+            "Minimum block size is too small for the header of a free block"
+        );
+        1 << MIN_ORDER
+    };
+
+    /// Create a new UNINITIALIZED heap allocator
+    ///
+    /// You must initialize this heap using the
+    /// [`init`](Self::init) method before using the allocator.
+    pub const fn empty() -> Heap<MIN_ORDER> {
+        Heap {
+            heap: Mutex::new(RefCell::new(Inner {
+                free_lists: [None; ORDERS],
+                bitmap: ptr::null_mut(),
+                base: 0,
+                end: 0,
+                used: 0,
+                initialized: false,
+            })),
+        }
+    }
+
+    /// Initializes the heap
+    ///
+    /// This function must be called BEFORE you run any code that makes use of the
+    /// allocator.
+    ///
+    /// `start_addr` is the address where the heap will be located.
+    ///
+    /// `size` is the size of the heap in bytes.
+    ///
+    /// A bitmap with one bit for every block of the minimum size is kept at the start
+    /// of the region. The rest is split into the largest naturally aligned blocks that
+    /// fit, so a region that is aligned to its power of two size makes the largest
+    /// allocations possible.
+    ///
+    /// # Safety
+    ///
+    /// This function is safe if the following invariants hold:
+    ///
+    /// - `start_addr` points to valid memory.
+    /// - `size` is correct.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if either of the following are true:
+    ///
+    /// - this function is called more than ONCE.
+    /// - `size == 0`.
+    /// - no block of the minimum size fits into the region after the bitmap.
+    pub unsafe fn init(&self, start_addr: usize, size: usize) {
+        if let Err(error) = self.try_init(start_addr, size) {
+            error.panic();
+        }
+    }
+
+    /// Initializes the heap like [`init`](Self::init), but returns an error instead of
+    /// panicking.
+    ///
+    /// # Safety
+    ///
+    /// This function is safe if the following invariants hold:
+    ///
+    /// - `start_addr` points to valid memory.
+    /// - `size` is correct.
+    pub unsafe fn try_init(&self, start_addr: usize, size: usize) -> Result<(), InitError> {
+        if size == 0 {
+            return Err(InitError::ZeroSize);
+        }
+        critical_section::with(|cs| {
+            let mut heap = self.heap.borrow_ref_mut(cs);
+            if heap.initialized {
+                return Err(InitError::AlreadyInitialized);
+            }
+            let end = start_addr.saturating_add(size) & !(Self::MIN_SIZE - 1);
+            let blocks = end.saturating_sub(start_addr) >> MIN_ORDER;
+            let bitmap_len = blocks.div_ceil(8);
+            let base = (start_addr + bitmap_len).next_multiple_of(Self::MIN_SIZE);
+            if base >= end {
+                return Err(InitError::TooSmallAfterAlignment {
+                    usable: end.saturating_sub(start_addr),
+                });
+            }
+            let bitmap = start_addr as *mut u8;
+            bitmap.write_bytes(0, bitmap_len);
+            heap.bitmap = bitmap;
+            heap.base = base;
+            heap.end = end;
+
+            let mut addr = base;
+            while addr < end {
+                let aligned = addr.trailing_zeros().min(usize::BITS - 1);
+                let fits = (end - addr).ilog2();
+                let order = aligned.min(fits);
+                heap.push(addr, order, MIN_ORDER);
+                addr += 1 << order;
+            }
+            heap.initialized = true;
+            Ok(())
+        })
+    }
+
+    /// Returns an estimate of the amount of bytes in use.
+    ///
+    /// Allocations are counted with their size rounded up to a power of two.
+    pub fn used(&self) -> usize {
+        critical_section::with(|cs| self.heap.borrow_ref_mut(cs).used)
+    }
+
+    /// Returns an estimate of the amount of bytes available.
+    pub fn free(&self) -> usize {
+        critical_section::with(|cs| {
+            let heap = self.heap.borrow_ref_mut(cs);
+            heap.end - heap.base - heap.used
+        })
+    }
+
+    /// Returns the order of the block that holds an allocation of `layout`.
+    fn order(layout: Layout) -> Option<u32> {
+        let size = layout.size().max(layout.align()).max(Self::MIN_SIZE);
+        Some(size.checked_next_power_of_two()?.trailing_zeros())
+    }
+
+    fn alloc(&self, layout: Layout) -> Option<NonNull<u8>> {
+        let order = Self::order(layout)?;
+        critical_section::with(|cs| {
+            let mut heap = self.heap.borrow_ref_mut(cs);
+            let (mut block_order, block) = (order as usize..ORDERS)
+                .find_map(|order| Some((order as u32, heap.free_lists[order]?)))?;
+            // Safety: The block is taken from the free lists, and its upper halves are
+            // handed back to them.
+            unsafe {
+                heap.remove(block, MIN_ORDER);
+                let addr = block.as_ptr() as usize;
+                while block_order > order {
+                    block_order -= 1;
+                    heap.push(addr + (1 << block_order), block_order, MIN_ORDER);
+                }
+            }
+            heap.used += 1 << order;
+            Some(block.cast())
+        })
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let order = Self::order(layout).unwrap_unchecked();
+        critical_section::with(|cs| {
+            let mut heap = self.heap.borrow_ref_mut(cs);
+            heap.free_block(ptr as usize, order, MIN_ORDER);
+            heap.used -= 1 << order;
+        });
+    }
+
+    /// Resizes the allocation at `ptr` to `new_size` bytes, keeping its alignment.
+    ///
+    /// The allocation stays in place if it still fits into its block, and shrinking
+    /// frees the unused halves of the block. Otherwise a new block is allocated, the
+    /// contents are copied over and the old block is freed. On failure the old block is
+    /// left untouched.
+    unsafe fn realloc(
+        &self,
+        ptr: NonNull<u8>,
+        layout: Layout,
+        new_size: usize,
+    ) -> Option<NonNull<u8>> {
+        let new_layout = Layout::from_size_align(new_size, layout.align()).ok()?;
+        let old_order = Self::order(layout)?;
+        let new_order = Self::order(new_layout)?;
+        if new_order <= old_order {
+            critical_section::with(|cs| {
+                let mut heap = self.heap.borrow_ref_mut(cs);
+                let addr = ptr.as_ptr() as usize;
+                for order in new_order..old_order {
+                    heap.free_block(addr + (1 << order), order, MIN_ORDER);
+                }
+                heap.used -= (1 << old_order) - (1 << new_order);
+            });
+            return Some(ptr);
+        }
+
+        let allocation = self.alloc(new_layout)?;
+        ptr::copy_nonoverlapping(ptr.as_ptr(), allocation.as_ptr(), layout.size());
+        self.dealloc(ptr.as_ptr(), layout);
+        Some(allocation)
+    }
+}
+
+impl<const MIN_ORDER: u32> crate::Heap for Heap<MIN_ORDER> {
+    unsafe fn init(&self, start_addr: usize, size: usize) {
+        self.init(start_addr, size);
+    }
+
+    unsafe fn try_init(&self, start_addr: usize, size: usize) -> Result<(), InitError> {
+        self.try_init(start_addr, size)
+    }
+
+    fn used(&self) -> usize {
+        self.used()
+    }
+
+    fn free(&self) -> usize {
+        self.free()
+    }
+}
+
+unsafe impl<const MIN_ORDER: u32> GlobalAlloc for Heap<MIN_ORDER> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.alloc(layout)
+            .map_or(ptr::null_mut(), |allocation| allocation.as_ptr())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.dealloc(ptr, layout);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        self.realloc(NonNull::new_unchecked(ptr), layout, new_size)
+            .map_or(ptr::null_mut(), |allocation| allocation.as_ptr())
+    }
+}
+
+#[cfg(feature = "allocator_api")]
+mod allocator_api {
+    use super::*;
+    use crate::allocator;
+    use core::alloc::{AllocError, Allocator};
+
+    unsafe impl<const MIN_ORDER: u32> Allocator for Heap<MIN_ORDER> {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            match layout.size() {
+                0 => Ok(NonNull::slice_from_raw_parts(layout.dangling_ptr(), 0)),
+                size => self.alloc(layout).map_or(Err(AllocError), |allocation| {
+                    Ok(NonNull::slice_from_raw_parts(allocation, size))
+                }),
+            }
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            if layout.size() != 0 {
+                self.dealloc(ptr.as_ptr(), layout);
+            }
+        }
+
+        unsafe fn grow(
+            &self,
+            ptr: NonNull<u8>,
+            old_layout: Layout,
+            new_layout: Layout,
+        ) -> Result<NonNull<[u8]>, AllocError> {
+            allocator::resize(self, ptr, old_layout, new_layout, |ptr, layout, size| {
+                self.realloc(ptr, layout, size)
+            })
+        }
+
+        unsafe fn grow_zeroed(
+            &self,
+            ptr: NonNull<u8>,
+            old_layout: Layout,
+            new_layout: Layout,
+        ) -> Result<NonNull<[u8]>, AllocError> {
+            allocator::resize_zeroed(self, ptr, old_layout, new_layout, |ptr, layout, size| {
+                self.realloc(ptr, layout, size)
+            })
+        }
+
+        unsafe fn shrink(
+            &self,
+            ptr: NonNull<u8>,
+            old_layout: Layout,
+            new_layout: Layout,
+        ) -> Result<NonNull<[u8]>, AllocError> {
+            allocator::resize(self, ptr, old_layout, new_layout, |ptr, layout, size| {
+                self.realloc(ptr, layout, size)
+            })
+        }
+    }
+}
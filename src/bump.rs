@@ -0,0 +1,288 @@
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::RefCell;
+use core::ptr::{self, NonNull};
+
+use critical_section::Mutex;
+
+use crate::InitError;
+
+struct Inner {
+    start: usize,
+    end: usize,
+    /// Address of the first free byte.
+    next: usize,
+    initialized: bool,
+}
+
+/// A bump pointer heap.
+///
+/// Allocation moves a pointer through the heap memory, so it takes constant time.
+/// Deallocation only gives memory back if it frees the most recent allocation, all
+/// other memory is reclaimed at once by [`reset`](Self::reset).
+pub struct Heap {
+    heap: Mutex<RefCell<Inner>>,
+}
+
+impl Heap {
+    /// Create a new UNINITIALIZED heap allocator
+    ///
+    /// You must initialize this heap using the
+    /// [`init`](Self::init) method before using the allocator.
+    pub const fn empty() -> Heap {
+        Heap {
+            heap: Mutex::new(RefCell::new(Inner {
+                start: 0,
+                end: 0,
+                next: 0,
+                initialized: false,
+            })),
+        }
+    }
+
+    /// Initializes the heap
+    ///
+    /// This function must be called BEFORE you run any code that makes use of the
+    /// allocator.
+    ///
+    /// `start_addr` is the address where the heap will be located.
+    ///
+    /// `size` is the size of the heap in bytes.
+    ///
+    /// Note that:
+    ///
+    /// - The heap grows "upwards", towards larger addresses. Thus `start_addr` will
+    ///   be the smallest address used.
+    ///
+    /// - The largest address used is `start_addr + size - 1`, so if `start_addr` is
+    ///   `0x1000` and `size` is `0x30000` then the allocator won't use memory at
+    ///   addresses `0x31000` and larger.
+    ///
+    /// # Safety
+    ///
+    /// This function is safe if the following invariants hold:
+    ///
+    /// - `start_addr` points to valid memory.
+    /// - `size` is correct.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if either of the following are true:
+    ///
+    /// - this function is called more than ONCE.
+    /// - `size == 0`.
+    pub unsafe fn init(&self, start_addr: usize, size: usize) {
+        if let Err(error) = self.try_init(start_addr, size) {
+            error.panic();
+        }
+    }
+
+    /// Initializes the heap like [`init`](Self::init), but returns an error instead of
+    /// panicking.
+    ///
+    /// # Safety
+    ///
+    /// This function is safe if the following invariants hold:
+    ///
+    /// - `start_addr` points to valid memory.
+    /// - `size` is correct.
+    pub unsafe fn try_init(&self, start_addr: usize, size: usize) -> Result<(), InitError> {
+        if size == 0 {
+            return Err(InitError::ZeroSize);
+        }
+        critical_section::with(|cs| {
+            let mut heap = self.heap.borrow_ref_mut(cs);
+            if heap.initialized {
+                return Err(InitError::AlreadyInitialized);
+            }
+            heap.start = start_addr;
+            heap.end = start_addr.saturating_add(size);
+            heap.next = start_addr;
+            heap.initialized = true;
+            Ok(())
+        })
+    }
+
+    /// Frees all allocations at once
+    ///
+    /// # Safety
+    ///
+    /// No allocation from this heap may be used after the reset. This includes
+    /// collections that still hold memory, so they must have been dropped or forgotten.
+    pub unsafe fn reset(&self) {
+        critical_section::with(|cs| {
+            let mut heap = self.heap.borrow_ref_mut(cs);
+            heap.next = heap.start;
+        });
+    }
+
+    /// Returns the amount of bytes in use, including alignment padding.
+    pub fn used(&self) -> usize {
+        critical_section::with(|cs| {
+            let heap = self.heap.borrow_ref_mut(cs);
+            heap.next - heap.start
+        })
+    }
+
+    /// Returns the amount of bytes available.
+    pub fn free(&self) -> usize {
+        critical_section::with(|cs| {
+            let heap = self.heap.borrow_ref_mut(cs);
+            heap.end - heap.next
+        })
+    }
+
+    fn alloc(&self, layout: Layout) -> Option<NonNull<u8>> {
+        critical_section::with(|cs| {
+            let mut heap = self.heap.borrow_ref_mut(cs);
+            let start = heap.next.checked_next_multiple_of(layout.align())?;
+            let end = start.checked_add(layout.size())?;
+            if end > heap.end {
+                return None;
+            }
+            heap.next = end;
+            NonNull::new(start as *mut u8)
+        })
+    }
+
+    /// Frees the allocation at `ptr` if it is the most recent one.
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        critical_section::with(|cs| {
+            let mut heap = self.heap.borrow_ref_mut(cs);
+            if ptr as usize + layout.size() == heap.next {
+                heap.next = ptr as usize;
+            }
+        });
+    }
+
+    /// Resizes the allocation at `ptr` to `new_size` bytes, keeping its alignment.
+    ///
+    /// Shrinking always happens in place; the freed tail is only returned to the heap
+    /// if `ptr` is the most recent allocation. The most recent allocation is also grown
+    /// in place if the heap has enough memory left. Otherwise a new block is allocated
+    /// and the contents are copied over. On failure the old block is left untouched.
+    unsafe fn realloc(
+        &self,
+        ptr: NonNull<u8>,
+        layout: Layout,
+        new_size: usize,
+    ) -> Option<NonNull<u8>> {
+        let new_layout = Layout::from_size_align(new_size, layout.align()).ok()?;
+        let resized = critical_section::with(|cs| {
+            let mut heap = self.heap.borrow_ref_mut(cs);
+            let start = ptr.as_ptr() as usize;
+            let last = start + layout.size() == heap.next;
+            if new_size <= layout.size() {
+                if last {
+                    heap.next = start + new_size;
+                }
+                return true;
+            }
+            let fits = start
+                .checked_add(new_size)
+                .is_some_and(|end| end <= heap.end);
+            if last && fits {
+                heap.next = start + new_size;
+            }
+            last && fits
+        });
+        if resized {
+            return Some(ptr);
+        }
+
+        let allocation = self.alloc(new_layout)?;
+        ptr::copy_nonoverlapping(ptr.as_ptr(), allocation.as_ptr(), layout.size());
+        self.dealloc(ptr.as_ptr(), layout);
+        Some(allocation)
+    }
+}
+
+impl crate::Heap for Heap {
+    unsafe fn init(&self, start_addr: usize, size: usize) {
+        self.init(start_addr, size);
+    }
+
+    unsafe fn try_init(&self, start_addr: usize, size: usize) -> Result<(), InitError> {
+        self.try_init(start_addr, size)
+    }
+
+    fn used(&self) -> usize {
+        self.used()
+    }
+
+    fn free(&self) -> usize {
+        self.free()
+    }
+}
+
+unsafe impl GlobalAlloc for Heap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.alloc(layout)
+            .map_or(ptr::null_mut(), |allocation| allocation.as_ptr())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.dealloc(ptr, layout);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        self.realloc(NonNull::new_unchecked(ptr), layout, new_size)
+            .map_or(ptr::null_mut(), |allocation| allocation.as_ptr())
+    }
+}
+
+#[cfg(feature = "allocator_api")]
+mod allocator_api {
+    use super::*;
+    use crate::allocator;
+    use core::alloc::{AllocError, Allocator};
+
+    unsafe impl Allocator for Heap {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            match layout.size() {
+                0 => Ok(NonNull::slice_from_raw_parts(layout.dangling_ptr(), 0)),
+                size => self.alloc(layout).map_or(Err(AllocError), |allocation| {
+                    Ok(NonNull::slice_from_raw_parts(allocation, size))
+                }),
+            }
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            if layout.size() != 0 {
+                self.dealloc(ptr.as_ptr(), layout);
+            }
+        }
+
+        unsafe fn grow(
+            &self,
+            ptr: NonNull<u8>,
+            old_layout: Layout,
+            new_layout: Layout,
+        ) -> Result<NonNull<[u8]>, AllocError> {
+            allocator::resize(self, ptr, old_layout, new_layout, |ptr, layout, size| {
+                self.realloc(ptr, layout, size)
+            })
+        }
+
+        unsafe fn grow_zeroed(
+            &self,
+            ptr: NonNull<u8>,
+            old_layout: Layout,
+            new_layout: Layout,
+        ) -> Result<NonNull<[u8]>, AllocError> {
+            allocator::resize_zeroed(self, ptr, old_layout, new_layout, |ptr, layout, size| {
+                self.realloc(ptr, layout, size)
+            })
+        }
+
+        unsafe fn shrink(
+            &self,
+            ptr: NonNull<u8>,
+            old_layout: Layout,
+            new_layout: Layout,
+        ) -> Result<NonNull<[u8]>, AllocError> {
+            allocator::resize(self, ptr, old_layout, new_layout, |ptr, layout, size| {
+                self.realloc(ptr, layout, size)
+            })
+        }
+    }
+}
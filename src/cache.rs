@@ -0,0 +1,95 @@
+use core::alloc::Layout;
+use core::mem::align_of;
+use core::ptr::{self, NonNull};
+
+use portable_atomic::{AtomicPtr, Ordering};
+
+/// Block sizes of the size classes, in bytes.
+const CLASSES: [usize; 4] = [16, 32, 64, 128];
+/// Number of cached blocks per size class.
+const SLOTS: usize = 4;
+const ALIGN: usize = align_of::<usize>();
+
+/// Returns the size class of `layout`, if it has one.
+fn class(layout: Layout) -> Option<usize> {
+    if layout.align() > ALIGN {
+        return None;
+    }
+    CLASSES.iter().position(|&size| layout.size() <= size)
+}
+
+/// Returns the layout of the block that holds an allocation of `layout`.
+///
+/// Allocations that fit a size class always use a block of the class, so cached blocks
+/// and blocks from the heap can be freed either way.
+pub(crate) fn block_layout(layout: Layout) -> Layout {
+    match class(layout) {
+        // Safety: The sizes of the classes are multiples of the alignment.
+        Some(class) => unsafe { Layout::from_size_align_unchecked(CLASSES[class], ALIGN) },
+        None => layout,
+    }
+}
+
+/// Lock-free slots holding free blocks of a few small size classes.
+///
+/// A slot is taken by swapping it with null and filled by exchanging null with a block,
+/// so every block has a single owner and there is no ABA problem as with a linked list.
+pub(crate) struct Cache {
+    slots: [[AtomicPtr<u8>; SLOTS]; CLASSES.len()],
+}
+
+impl Cache {
+    pub(crate) const fn new() -> Cache {
+        Cache {
+            slots: [const { [const { AtomicPtr::new(ptr::null_mut()) }; SLOTS] }; CLASSES.len()],
+        }
+    }
+
+    /// Takes a block for `layout`, if its class has one.
+    pub(crate) fn take(&self, layout: Layout) -> Option<NonNull<u8>> {
+        self.slots[class(layout)?]
+            .iter()
+            .find_map(|slot| NonNull::new(slot.swap(ptr::null_mut(), Ordering::Acquire)))
+    }
+
+    /// Puts the block `ptr` of `layout` into a free slot, returning `false` if there is none.
+    pub(crate) fn put(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        let Some(class) = class(layout) else {
+            return false;
+        };
+        self.slots[class].iter().any(|slot| {
+            slot.compare_exchange(
+                ptr::null_mut(),
+                ptr.as_ptr(),
+                Ordering::Release,
+                Ordering::Relaxed,
+            )
+            .is_ok()
+        })
+    }
+
+    /// Returns the block layout of every empty slot.
+    pub(crate) fn vacancies(&self) -> impl Iterator<Item = Layout> + '_ {
+        CLASSES.iter().zip(&self.slots).flat_map(|(&size, slots)| {
+            // Safety: The sizes of the classes are multiples of the alignment.
+            let layout = unsafe { Layout::from_size_align_unchecked(size, ALIGN) };
+            slots
+                .iter()
+                .filter(|slot| slot.load(Ordering::Relaxed).is_null())
+                .map(move |_| layout)
+        })
+    }
+
+    /// Empties all slots, passing their blocks to `deallocate`.
+    pub(crate) fn drain(&self, mut deallocate: impl FnMut(NonNull<u8>, Layout)) {
+        for (&size, slots) in CLASSES.iter().zip(&self.slots) {
+            // Safety: The sizes of the classes are multiples of the alignment.
+            let layout = unsafe { Layout::from_size_align_unchecked(size, ALIGN) };
+            for slot in slots {
+                if let Some(block) = NonNull::new(slot.swap(ptr::null_mut(), Ordering::Acquire)) {
+                    deallocate(block, layout);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,27 @@
+/// Corrupted heap metadata found by `Heap::check`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HeapCorruption {
+    /// Address of the first block with invalid metadata.
+    pub address: usize,
+    /// What is wrong with the block.
+    pub kind: CorruptionKind,
+}
+
+/// The kind of a [`HeapCorruption`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CorruptionKind {
+    /// The block is not aligned to the block granularity of the heap.
+    Misaligned,
+    /// The block size is zero, too small or not a multiple of the block granularity.
+    InvalidSize,
+    /// The block lies partly or completely outside of the heap memory.
+    OutOfBounds,
+    /// The block overlaps with the previous block, or the free list is out of order.
+    Overlap,
+    /// The free block directly follows another free block instead of being merged with it.
+    NotMerged,
+    /// The block sizes don't add up to the size of the heap. The address is the start of
+    /// the heap region that doesn't add up.
+    SizeMismatch,
+}
@@ -0,0 +1,38 @@
+/// Fragmentation of the free memory of a heap
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Fragmentation {
+    /// Number of free blocks.
+    pub free_blocks: usize,
+    /// Size of the largest free block in bytes.
+    pub largest_free_block: usize,
+    /// Total size of all free blocks in bytes.
+    pub total_free: usize,
+}
+
+impl Fragmentation {
+    /// Returns the fragmentation in percent, `100 * (1 - largest_free_block / total_free)`.
+    ///
+    /// 0 means that all free memory is in a single block, values close to 100 mean that
+    /// it is scattered over many small blocks. A heap without free memory is reported
+    /// as not fragmented.
+    pub fn percent(&self) -> u8 {
+        if self.total_free == 0 {
+            return 0;
+        }
+        let largest = self.largest_free_block as u64 * 100 / self.total_free as u64;
+        100 - largest as u8
+    }
+}
+
+/// Collects the sizes of the free blocks of a heap.
+impl FromIterator<usize> for Fragmentation {
+    fn from_iter<I: IntoIterator<Item = usize>>(free_block_sizes: I) -> Fragmentation {
+        let mut fragmentation = Fragmentation::default();
+        for size in free_block_sizes {
+            fragmentation.free_blocks += 1;
+            fragmentation.largest_free_block = fragmentation.largest_free_block.max(size);
+            fragmentation.total_free += size;
+        }
+        fragmentation
+    }
+}
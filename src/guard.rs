@@ -0,0 +1,180 @@
+use core::alloc::Layout;
+use core::mem::{align_of, size_of};
+use core::ptr::{self, NonNull};
+
+/// Function called when the canaries of an allocation were overwritten
+///
+/// It is called with the address and layout of the allocation, as passed to
+/// `dealloc`. With the `isr_cache` feature, allocations that fit a size class are
+/// reported with the layout of their class instead. Without a handler the heap panics.
+pub type GuardHandler = fn(usize, Layout);
+
+/// The value of every canary word.
+const CANARY: usize = usize::MAX / 0xFF * 0xC5;
+
+const WORD: usize = size_of::<usize>();
+
+/// Header stored in front of the leading canaries of every guarded allocation.
+struct Header {
+    prev: Option<NonNull<Header>>,
+    next: Option<NonNull<Header>>,
+    layout: Layout,
+}
+
+/// The canary configuration of a heap and the list of its live allocations.
+///
+/// A guarded allocation is laid out as
+///
+/// ```text
+/// | padding | Header | leading canaries | data | trailing canaries |
+/// ```
+///
+/// with the data aligned as requested. The trailing canaries directly follow the data,
+/// so they are written unaligned.
+pub(crate) struct Guard {
+    head: Option<NonNull<Header>>,
+    leading: usize,
+    trailing: usize,
+    pub(crate) handler: Option<GuardHandler>,
+}
+
+impl Guard {
+    pub(crate) const fn new() -> Guard {
+        Guard {
+            head: None,
+            leading: 1,
+            trailing: 1,
+            handler: None,
+        }
+    }
+
+    /// Sets the number of canary words in front of and behind every allocation.
+    pub(crate) fn set_canaries(&mut self, leading: usize, trailing: usize) {
+        assert!(
+            self.head.is_none(),
+            "Canaries can't be changed while allocations are live"
+        );
+        self.leading = leading;
+        self.trailing = trailing;
+    }
+
+    /// Forgets all live allocations, keeping the configuration.
+    pub(crate) fn reset(&mut self) {
+        self.head = None;
+    }
+
+    /// Returns the layout of the block holding a guarded allocation of `layout`.
+    pub(crate) fn outer_layout(&self, layout: Layout) -> Option<Layout> {
+        let align = layout.align().max(align_of::<Header>());
+        let size = self
+            .offset(layout)?
+            .checked_add(layout.size())?
+            .checked_add(self.trailing.checked_mul(WORD)?)?;
+        Layout::from_size_align(size, align).ok()
+    }
+
+    /// Returns the offset of the data from the start of the block.
+    fn offset(&self, layout: Layout) -> Option<usize> {
+        let align = layout.align().max(align_of::<Header>());
+        self.leading
+            .checked_mul(WORD)?
+            .checked_add(size_of::<Header>())?
+            .checked_next_multiple_of(align)
+    }
+
+    /// Writes the header and canaries into `block` and returns the pointer to the data.
+    ///
+    /// # Safety
+    ///
+    /// `block` must be a live allocation of [`outer_layout`](Self::outer_layout).
+    pub(crate) unsafe fn insert(&mut self, block: NonNull<u8>, layout: Layout) -> NonNull<u8> {
+        let data = block.as_ptr().add(self.offset(layout).unwrap_unchecked());
+        let header = self.header(data);
+        header.write(Header {
+            prev: None,
+            next: self.head,
+            layout,
+        });
+        if let Some(next) = self.head {
+            (*next.as_ptr()).prev = Some(NonNull::new_unchecked(header));
+        }
+        self.head = Some(NonNull::new_unchecked(header));
+
+        let leading = data.cast::<usize>().sub(self.leading);
+        for i in 0..self.leading {
+            leading.add(i).write(CANARY);
+        }
+        let trailing = data.add(layout.size()).cast::<usize>();
+        for i in 0..self.trailing {
+            trailing.add(i).write_unaligned(CANARY);
+        }
+        NonNull::new_unchecked(data)
+    }
+
+    /// Unlinks the allocation at `data` and checks its canaries.
+    ///
+    /// Returns the block to free, or `None` if the canaries were overwritten. Such
+    /// blocks are never returned to the heap, as the damage may reach further.
+    ///
+    /// # Safety
+    ///
+    /// `data` must have been returned by [`insert`](Self::insert) for `layout`.
+    pub(crate) unsafe fn remove(
+        &mut self,
+        data: NonNull<u8>,
+        layout: Layout,
+    ) -> Option<NonNull<u8>> {
+        let intact = self.intact(data.as_ptr(), layout);
+        let header = self.header(data.as_ptr());
+        let Header { prev, next, .. } = header.read();
+        match prev {
+            Some(prev) => (*prev.as_ptr()).next = next,
+            None => self.head = next,
+        }
+        if let Some(next) = next {
+            (*next.as_ptr()).prev = prev;
+        }
+        if !intact {
+            return None;
+        }
+        let offset = self.offset(layout).unwrap_unchecked();
+        Some(NonNull::new_unchecked(data.as_ptr().sub(offset)))
+    }
+
+    /// Returns the address and layout of the first live allocation with overwritten
+    /// canaries.
+    pub(crate) fn find_corrupted(&self) -> Option<(usize, Layout)> {
+        let mut cursor = self.head;
+        while let Some(header) = cursor {
+            // Safety: The list only contains headers of live allocations.
+            unsafe {
+                let Header { next, layout, .. } = header.as_ptr().read();
+                let data = header.as_ptr().add(1).cast::<u8>().add(self.leading * WORD);
+                if !self.intact(data, layout) {
+                    return Some((data as usize, layout));
+                }
+                cursor = next;
+            }
+        }
+        None
+    }
+
+    unsafe fn header(&self, data: *mut u8) -> *mut Header {
+        data.sub(self.leading * WORD).cast::<Header>().sub(1)
+    }
+
+    unsafe fn intact(&self, data: *mut u8, layout: Layout) -> bool {
+        let leading = data.cast::<usize>().sub(self.leading);
+        let trailing = data.add(layout.size()).cast::<usize>();
+        (0..self.leading).all(|i| leading.add(i).read() == CANARY)
+            && (0..self.trailing).all(|i| ptr::read_unaligned(trailing.add(i)) == CANARY)
+    }
+}
+
+/// Hands an allocation with overwritten canaries to `handler`, or panics without one.
+pub(crate) fn report(handler: Option<GuardHandler>, address: usize, layout: Layout) {
+    match handler {
+        Some(handler) => handler(address, layout),
+        None => panic!("Heap canaries overwritten at {address:#x}, {layout:?}"),
+    }
+}
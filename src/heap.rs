@@ -0,0 +1,113 @@
+use core::alloc::{GlobalAlloc, Layout};
+
+#[cfg(feature = "stats")]
+use crate::HeapStats;
+use crate::{Fragmentation, HeapCorruption};
+
+/// The interface shared by all heaps of this crate.
+///
+/// This allows writing code that works with any heap, such as the [`init!`](crate::init)
+/// macro. Each heap documents the details of these methods for itself.
+pub trait Heap: GlobalAlloc {
+    /// Initializes the heap with the memory region at `start_addr` of `size` bytes.
+    ///
+    /// # Safety
+    ///
+    /// This function is safe if the following invariants hold:
+    ///
+    /// - `start_addr` points to valid memory.
+    /// - `size` is correct.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if it is called more than ONCE, or if the region is too
+    /// small for the heap.
+    unsafe fn init(&self, start_addr: usize, size: usize);
+
+    /// Initializes the heap like [`init`](Self::init), but returns an error instead of
+    /// panicking.
+    ///
+    /// # Safety
+    ///
+    /// This function is safe if the following invariants hold:
+    ///
+    /// - `start_addr` points to valid memory.
+    /// - `size` is correct.
+    unsafe fn try_init(&self, start_addr: usize, size: usize) -> Result<(), InitError>;
+
+    /// Returns the amount of bytes in use.
+    fn used(&self) -> usize;
+
+    /// Returns the amount of bytes available.
+    fn free(&self) -> usize;
+}
+
+/// Inspection of the free memory and metadata of a heap.
+///
+/// This is implemented by [`LlffHeap`](crate::LlffHeap) and [`TlsfHeap`](crate::TlsfHeap).
+pub trait Introspect: Heap {
+    /// Returns the size of the largest free block in bytes.
+    fn largest_free_block(&self) -> usize;
+
+    /// Returns whether an allocation with `layout` would currently succeed.
+    fn can_allocate(&self, layout: Layout) -> bool;
+
+    /// Returns how fragmented the free memory is.
+    fn fragmentation(&self) -> Fragmentation;
+
+    /// Checks the metadata of the heap for corruption.
+    fn check(&self) -> Result<(), HeapCorruption>;
+}
+
+/// Allocation statistics of a heap.
+///
+/// This is implemented by [`LlffHeap`](crate::LlffHeap) and [`TlsfHeap`](crate::TlsfHeap).
+#[cfg(feature = "stats")]
+pub trait Stats: Heap {
+    /// Returns the allocation statistics of the heap.
+    fn stats(&self) -> HeapStats;
+}
+
+/// Error returned by `try_reset` while allocations are live
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ResetError {
+    /// Number of allocations that haven't been freed.
+    pub live_allocations: usize,
+}
+
+/// Error returned by `try_init` for memory the heap can't be initialized with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum InitError {
+    /// The heap was initialized before.
+    AlreadyInitialized,
+    /// The size of the memory is zero.
+    ZeroSize,
+    /// Too little memory is left after aligning its start and end to the blocks of the
+    /// heap.
+    TooSmallAfterAlignment {
+        /// Number of bytes left after the alignment.
+        usable: usize,
+    },
+    /// The start of the memory is not aligned to `align_of::<usize>()`.
+    Misaligned,
+}
+
+impl InitError {
+    /// Panics with a description of the error, for the `init` functions.
+    #[cfg(any(
+        feature = "llff",
+        feature = "tlsf",
+        feature = "pool",
+        feature = "bump",
+        feature = "buddy"
+    ))]
+    pub(crate) fn panic(self) -> ! {
+        match self {
+            InitError::AlreadyInitialized => panic!("Heap is already initialized"),
+            InitError::ZeroSize => panic!("Heap size is zero"),
+            InitError::TooSmallAfterAlignment { .. } => panic!("Allocation too small for heap"),
+            InitError::Misaligned => panic!("Heap start is not aligned to a word"),
+        }
+    }
+}
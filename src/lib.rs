@@ -3,15 +3,82 @@
 #![cfg_attr(feature = "allocator_api", feature(allocator_api))]
 #![warn(missing_docs)]
 
+#[cfg(feature = "allocator_api")]
+mod allocator;
+#[cfg(feature = "buddy")]
+mod buddy;
+#[cfg(feature = "bump")]
+mod bump;
+#[cfg(feature = "isr_cache")]
+#[cfg_attr(not(any(feature = "llff", feature = "tlsf")), allow(dead_code))]
+mod cache;
+mod corruption;
+mod fragmentation;
+#[cfg(feature = "guard")]
+#[cfg_attr(not(any(feature = "llff", feature = "tlsf")), allow(dead_code))]
+mod guard;
+mod heap;
 #[cfg(feature = "llff")]
 mod llff;
+mod local_heap;
+mod lock;
+#[cfg(feature = "stats")]
+mod oom;
+#[cfg(feature = "poison")]
+#[cfg_attr(not(any(feature = "llff", feature = "tlsf")), allow(dead_code))]
+mod poison;
+#[cfg(feature = "pool")]
+mod pool;
+mod static_heap;
+#[cfg(feature = "stats")]
+#[cfg_attr(not(any(feature = "llff", feature = "tlsf")), allow(dead_code))]
+mod stats;
 #[cfg(feature = "tlsf")]
 mod tlsf;
 
+#[cfg(feature = "buddy")]
+pub use buddy::Heap as BuddyHeap;
+#[cfg(feature = "bump")]
+pub use bump::Heap as BumpHeap;
+pub use corruption::{CorruptionKind, HeapCorruption};
+pub use fragmentation::Fragmentation;
+#[cfg(feature = "guard")]
+pub use guard::GuardHandler;
+#[cfg(feature = "stats")]
+pub use heap::Stats;
+pub use heap::{Heap, InitError, Introspect, ResetError};
 #[cfg(feature = "llff")]
-pub use llff::Heap as LlffHeap;
+pub use llff::Heap as LlffHeapWith;
+pub use local_heap::LocalHeap;
+pub use lock::{CriticalSectionRawMutex, RawMutex, TryAllocError};
+#[cfg(feature = "stats")]
+pub use oom::{OomAction, OomHandler};
+#[cfg(feature = "pool")]
+pub use pool::{Heap as PoolHeap, Pool, Pools};
+pub use static_heap::StaticHeap;
+#[cfg(feature = "stats")]
+pub use stats::HeapStats;
 #[cfg(feature = "tlsf")]
-pub use tlsf::Heap as TlsfHeap;
+pub use tlsf::Heap as TlsfHeapWith;
+
+/// A linked list first fit heap, locked with a critical section.
+#[cfg(feature = "llff")]
+pub type LlffHeap = LlffHeapWith<CriticalSectionRawMutex>;
+/// A two-Level segregated fit heap, locked with a critical section.
+#[cfg(feature = "tlsf")]
+pub type TlsfHeap = TlsfHeapWith<CriticalSectionRawMutex>;
+/// A linked list first fit heap with an `N` byte buffer, see [`StaticHeap`].
+#[cfg(feature = "llff")]
+pub type StaticLlffHeap<const N: usize> = StaticHeap<LlffHeap, N>;
+/// A two-level segregated fit heap with an `N` byte buffer, see [`StaticHeap`].
+#[cfg(feature = "tlsf")]
+pub type StaticTlsfHeap<const N: usize> = StaticHeap<TlsfHeap, N>;
+/// A linked list first fit heap over a borrowed buffer, see [`LocalHeap`].
+#[cfg(feature = "llff")]
+pub type LocalLlffHeap<'buf> = LocalHeap<'buf, LlffHeap>;
+/// A two-level segregated fit heap over a borrowed buffer, see [`LocalHeap`].
+#[cfg(feature = "tlsf")]
+pub type LocalTlsfHeap<'buf> = LocalHeap<'buf, TlsfHeap>;
 
 /// Initialize the global heap.
 ///
@@ -20,15 +87,23 @@ pub use tlsf::Heap as TlsfHeap;
 ///
 /// # Parameters
 ///
-/// - `$heap:ident`: The identifier of the global heap instance to initialize.
+/// - `$heap:ident`: The identifier of the global heap instance to initialize. It can be
+///   any type that implements [`Heap`].
 /// - `$size:expr`: An expression evaluating to a `usize` that specifies the size of the
 ///   static memory buffer in bytes. It must be **greater than zero**.
+/// - `link_section = "..."` (optional): The linker section of the buffer, for example
+///   to place it in CCM or DTCM, or in a `.uninit` section that isn't zeroed at startup.
+/// - `align = N` (optional): The alignment of the buffer in bytes, a power of two.
+///
+/// Each invocation creates its own buffer, so several heaps can be initialized in the
+/// same function.
 ///
 /// # Safety
 ///
 /// This macro must be called first, before any operations on the heap, and **only once**.
-/// It internally calls `Heap::init(...)` on the heap,
+/// It internally calls [`Heap::init`] on the heap,
 /// so `Heap::init(...)` should not be called directly if this macro is used.
+/// A [`StaticHeap`] owns its buffer and initializes itself on first use instead.
 ///
 /// # Panics
 ///
@@ -56,11 +131,172 @@ pub use tlsf::Heap as TlsfHeap;
 ///     // ...
 /// }
 /// ```
+///
+/// Placing the buffer in a section that isn't zeroed at startup, aligned to 8 bytes:
+///
+/// ```rust
+/// unsafe {
+///     embedded_alloc::init!(HEAP, 1024, link_section = ".uninit.heap", align = 8);
+/// }
+/// ```
 #[macro_export]
 macro_rules! init {
-    ($heap:ident, $size:expr) => {
-        static mut HEAP_MEM: [::core::mem::MaybeUninit<u8>; $size] =
-            [::core::mem::MaybeUninit::uninit(); $size];
-        $heap.init(&raw mut HEAP_MEM as usize, $size)
+    ($heap:ident, $size:expr $(,)?) => {
+        $crate::init!(@buffer $heap, $size, [], 1)
+    };
+    ($heap:ident, $size:expr, link_section = $section:literal $(,)?) => {
+        $crate::init!(@buffer $heap, $size, [$section], 1)
+    };
+    ($heap:ident, $size:expr, align = $align:literal $(,)?) => {
+        $crate::init!(@buffer $heap, $size, [], $align)
+    };
+    ($heap:ident, $size:expr, link_section = $section:literal, align = $align:literal $(,)?) => {
+        $crate::init!(@buffer $heap, $size, [$section], $align)
+    };
+    ($heap:ident, $size:expr, align = $align:literal, link_section = $section:literal $(,)?) => {
+        $crate::init!(@buffer $heap, $size, [$section], $align)
+    };
+    (@buffer $heap:ident, $size:expr, [$($section:literal)?], $align:literal) => {
+        $crate::Heap::init(&$heap, $crate::init!(@memory $size, [$($section)?], $align), $size)
+    };
+    (@memory $size:expr, [$($section:literal)?], $align:literal) => {{
+        // The empty array aligns the buffer to a word at least.
+        #[repr(C, align($align))]
+        struct HeapMemory([usize; 0], [::core::mem::MaybeUninit<u8>; $size]);
+
+        $(#[unsafe(link_section = $section)])?
+        static mut HEAP_MEM: HeapMemory =
+            HeapMemory([], [::core::mem::MaybeUninit::uninit(); $size]);
+        &raw mut HEAP_MEM as usize
+    }};
+}
+
+/// Initialize the global heap, returning an error instead of panicking.
+///
+/// This works like [`init!`] and takes the same arguments, but calls `try_init` on the
+/// heap and evaluates to its `Result<(), InitError>`. `try_init` is provided by
+/// `LlffHeap` and `TlsfHeap`, see [`InitError`](crate::InitError) for the errors.
+///
+/// # Safety
+///
+/// This macro must be called first, before any operations on the heap.
+///
+/// # Example
+///
+/// ```rust
+/// use cortex_m_rt::entry;
+/// use embedded_alloc::LlffHeap as Heap;
+///
+/// #[global_allocator]
+/// static HEAP: Heap = Heap::empty();
+///
+/// #[entry]
+/// fn main() -> ! {
+///     // Initialize the allocator BEFORE you use it
+///     if unsafe { embedded_alloc::try_init!(HEAP, 1024) }.is_err() {
+///         // Report the error, or run without a heap.
+///     }
+///     // ...
+/// }
+/// ```
+#[macro_export]
+macro_rules! try_init {
+    ($heap:ident, $size:expr $(,)?) => {
+        $crate::try_init!(@buffer $heap, $size, [], 1)
+    };
+    ($heap:ident, $size:expr, link_section = $section:literal $(,)?) => {
+        $crate::try_init!(@buffer $heap, $size, [$section], 1)
     };
+    ($heap:ident, $size:expr, align = $align:literal $(,)?) => {
+        $crate::try_init!(@buffer $heap, $size, [], $align)
+    };
+    ($heap:ident, $size:expr, link_section = $section:literal, align = $align:literal $(,)?) => {
+        $crate::try_init!(@buffer $heap, $size, [$section], $align)
+    };
+    ($heap:ident, $size:expr, align = $align:literal, link_section = $section:literal $(,)?) => {
+        $crate::try_init!(@buffer $heap, $size, [$section], $align)
+    };
+    (@buffer $heap:ident, $size:expr, [$($section:literal)?], $align:literal) => {
+        $heap.try_init($crate::init!(@memory $size, [$($section)?], $align), $size)
+    };
+}
+
+/// Initialize the global heap with the memory between two linker symbols.
+///
+/// This declares `$start` and `$end` as `extern` symbols and passes the memory from the
+/// address of `$start` up to the address of `$end` to the heap. The start is usually
+/// `__sheap`, which `cortex-m-rt` places after `.bss` and `.data`, and the end a symbol
+/// defined in `memory.x`, such as the end of RAM minus the stack size or the bounds of
+/// a dedicated `MEMORY` region.
+///
+/// # Parameters
+///
+/// - `$heap:ident`: The identifier of the global heap instance to initialize. It can be
+///   any type that implements [`Heap`].
+/// - `$start:ident`: The linker symbol at the start of the heap memory.
+/// - `$end:ident`: The linker symbol at the end of the heap memory, which is not part
+///   of the heap.
+///
+/// # Safety
+///
+/// This macro must be called first, before any operations on the heap, and **only once**.
+/// The memory between the symbols must be valid and not used for anything else, such as
+/// the stack.
+///
+/// # Panics
+///
+/// This macro will panic if either of the following are true:
+///
+/// - this function is called more than ONCE.
+/// - `$start` is not aligned to `align_of::<usize>()`.
+/// - `$end` is not above `$start`.
+///
+/// # Example
+///
+/// With `memory.x` containing:
+///
+/// ```text
+/// _heap_end = ORIGIN(RAM) + LENGTH(RAM) - 8K;
+/// ```
+///
+/// ```rust
+/// use cortex_m_rt::entry;
+/// use embedded_alloc::LlffHeap as Heap;
+///
+/// #[global_allocator]
+/// static HEAP: Heap = Heap::empty();
+///
+/// #[entry]
+/// fn main() -> ! {
+///     // Initialize the allocator BEFORE you use it
+///     unsafe {
+///         embedded_alloc::init_from_linker!(HEAP, __sheap, _heap_end);
+///     }
+///     let mut xs = Vec::new();
+///     // ...
+/// }
+/// ```
+#[macro_export]
+macro_rules! init_from_linker {
+    ($heap:ident, $start:ident, $end:ident) => {{
+        unsafe extern "C" {
+            static mut $start: u8;
+            static mut $end: u8;
+        }
+        $crate::__init_between(&$heap, &raw mut $start as usize, &raw mut $end as usize)
+    }};
+}
+
+/// Initializes `heap` with the memory from `start` up to `end`, for [`init_from_linker!`].
+#[doc(hidden)]
+pub unsafe fn __init_between(heap: &impl Heap, start: usize, end: usize) {
+    assert!(
+        start.is_multiple_of(core::mem::align_of::<usize>()),
+        "Heap start {start:#x} is not aligned to a word"
+    );
+    assert!(
+        end > start,
+        "Heap end {end:#x} is not above its start {start:#x}"
+    );
+    heap.init(start, end - start);
 }
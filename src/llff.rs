@@ -1,30 +1,191 @@
 use core::alloc::{GlobalAlloc, Layout};
 use core::cell::RefCell;
+use core::mem::{align_of, MaybeUninit};
 use core::ptr::{self, NonNull};
 
-use critical_section::Mutex;
-use linked_list_allocator::Heap as LLHeap;
+mod hole;
+
+#[cfg(feature = "isr_cache")]
+use crate::cache::{self, Cache};
+#[cfg(feature = "guard")]
+use crate::guard::{self, Guard};
+#[cfg(feature = "poison")]
+use crate::poison;
+#[cfg(feature = "guard")]
+use crate::GuardHandler;
+use crate::{
+    CorruptionKind, CriticalSectionRawMutex, Fragmentation, HeapCorruption, InitError, Introspect,
+    RawMutex, ResetError, TryAllocError,
+};
+#[cfg(feature = "stats")]
+use crate::{HeapStats, OomAction, OomHandler, Stats};
+use hole::HoleList;
+
+struct Inner {
+    holes: HoleList,
+    initialized: bool,
+    bottom: usize,
+    top: usize,
+    size: usize,
+    used: usize,
+    live: usize,
+    region: (usize, usize),
+    #[cfg(feature = "stats")]
+    stats: HeapStats,
+    #[cfg(feature = "stats")]
+    oom_handler: Option<OomHandler>,
+    #[cfg(feature = "guard")]
+    guard: Guard,
+}
+
+impl Inner {
+    fn allocate(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        #[cfg(feature = "isr_cache")]
+        let layout = cache::block_layout(layout);
+        #[cfg(feature = "guard")]
+        let (layout, data_layout) = (self.guard.outer_layout(layout)?, layout);
+        let allocation = self.holes.allocate_first_fit(layout)?;
+        let block_size = HoleList::block_size(layout);
+        self.used += block_size;
+        #[cfg(feature = "poison")]
+        // Safety: The block was just allocated and is free memory apart from that.
+        unsafe {
+            poison::check_freed(allocation.as_ptr(), block_size);
+            poison::fill(allocation.as_ptr(), block_size, poison::ALLOCATED);
+        }
+        #[cfg(feature = "guard")]
+        // Safety: The block was just allocated with the outer layout.
+        let allocation = unsafe { self.guard.insert(allocation, data_layout) };
+        self.live += 1;
+        Some(allocation)
+    }
+
+    /// Frees the allocation at `ptr`, returning `false` if its canaries were overwritten.
+    unsafe fn deallocate(&mut self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        #[cfg(feature = "isr_cache")]
+        let layout = cache::block_layout(layout);
+        #[cfg(feature = "guard")]
+        let (ptr, layout) = match self.guard.remove(ptr, layout) {
+            Some(block) => (block, self.guard.outer_layout(layout).unwrap_unchecked()),
+            None => return false,
+        };
+        let block_size = HoleList::block_size(layout);
+        #[cfg(feature = "poison")]
+        poison::fill(ptr.as_ptr(), block_size, poison::FREED);
+        self.holes.deallocate(ptr, layout);
+        self.used -= block_size;
+        self.live -= 1;
+        true
+    }
+
+    /// Adds the region at `start_addr` as the first free block of the heap.
+    unsafe fn init(&mut self, start_addr: usize, size: usize) -> Result<(), InitError> {
+        if self.initialized {
+            return Err(InitError::AlreadyInitialized);
+        }
+        if size == 0 {
+            return Err(InitError::ZeroSize);
+        }
+        #[cfg(feature = "poison")]
+        poison::fill(start_addr as *mut u8, size, poison::FREED);
+        let Some((start, usable)) = self.holes.add_region(start_addr, size) else {
+            return Err(InitError::TooSmallAfterAlignment {
+                usable: HoleList::usable(start_addr, size),
+            });
+        };
+        self.initialized = true;
+        self.region = (start_addr, size);
+        self.bottom = start;
+        self.top = start + usable;
+        self.size = usable;
+        Ok(())
+    }
+
+    /// Puts the region given to `init` back into the heap as a single free block.
+    unsafe fn reset(&mut self) {
+        if !self.initialized {
+            return;
+        }
+        let (start_addr, size) = self.region;
+        #[cfg(feature = "poison")]
+        poison::fill(start_addr as *mut u8, size, poison::FREED);
+        self.holes = HoleList::empty();
+        // Safety: The region was added successfully by `init`.
+        let (start, usable) = self.holes.add_region(start_addr, size).unwrap_unchecked();
+        self.bottom = start;
+        self.top = start + usable;
+        self.size = usable;
+        self.used = 0;
+        self.live = 0;
+        #[cfg(feature = "stats")]
+        {
+            self.stats = HeapStats::new();
+        }
+        #[cfg(feature = "guard")]
+        self.guard.reset();
+    }
+}
+
+// Safety: The whole inner type is only accessed while the heap is locked.
+unsafe impl Sync for Inner {}
+unsafe impl Send for Inner {}
 
 /// A linked list first fit heap.
-pub struct Heap {
-    heap: Mutex<RefCell<(LLHeap, bool)>>,
+pub struct Heap<R = CriticalSectionRawMutex> {
+    mutex: R,
+    heap: RefCell<Inner>,
+    #[cfg(feature = "isr_cache")]
+    cache: Cache,
 }
 
-impl Heap {
+// Safety: `heap` is only accessed while `mutex` is locked.
+unsafe impl<R: RawMutex + Sync> Sync for Heap<R> {}
+
+impl<R: RawMutex> Heap<R> {
     /// Create a new UNINITIALIZED heap allocator
     ///
     /// You must initialize this heap using the
     /// [`init`](Self::init) method before using the allocator.
-    pub const fn empty() -> Heap {
+    pub const fn empty() -> Heap<R> {
         Heap {
-            heap: Mutex::new(RefCell::new((LLHeap::empty(), false))),
+            mutex: R::INIT,
+            heap: RefCell::new(Inner {
+                holes: HoleList::empty(),
+                initialized: false,
+                bottom: 0,
+                top: 0,
+                size: 0,
+                used: 0,
+                live: 0,
+                region: (0, 0),
+                #[cfg(feature = "stats")]
+                stats: HeapStats::new(),
+                #[cfg(feature = "stats")]
+                oom_handler: None,
+                #[cfg(feature = "guard")]
+                guard: Guard::new(),
+            }),
+            #[cfg(feature = "isr_cache")]
+            cache: Cache::new(),
         }
     }
 
+    /// Runs `f` on the heap state with the heap locked.
+    fn with<T>(&self, f: impl FnOnce(&mut Inner) -> T) -> T {
+        self.mutex.lock(|| f(&mut self.heap.borrow_mut()))
+    }
+
+    /// Runs `f` on the heap state, or returns `None` if the heap is locked already.
+    fn try_with<T>(&self, f: impl FnOnce(&mut Inner) -> T) -> Option<T> {
+        self.mutex
+            .try_lock(|| self.heap.try_borrow_mut().ok().map(|mut heap| f(&mut heap)))
+            .flatten()
+    }
+
     /// Initializes the heap
     ///
     /// This function must be called BEFORE you run any code that makes use of the
-    /// allocator.
+    /// allocator. A [`StaticHeap`](crate::StaticHeap) initializes itself instead.
     ///
     /// `start_addr` is the address where the heap will be located.
     ///
@@ -52,47 +213,504 @@ impl Heap {
     ///
     /// - this function is called more than ONCE.
     /// - `size == 0`.
+    /// - `size`, after aligning start and end to `align_of::<usize>()`, is smaller than
+    ///   `2 * size_of::<usize>()`.
     pub unsafe fn init(&self, start_addr: usize, size: usize) {
+        if let Err(error) = self.with(|heap| heap.init(start_addr, size)) {
+            error.panic();
+        }
+    }
+
+    /// Initializes the heap like [`init`](Self::init), but returns an error instead of
+    /// panicking.
+    ///
+    /// Unlike `init`, which skips the bytes up to the next word, this refuses a
+    /// `start_addr` that is not aligned to `align_of::<usize>()` with
+    /// [`InitError::Misaligned`], as it usually is a wrong address.
+    ///
+    /// # Safety
+    ///
+    /// This function is safe if the following invariants hold:
+    ///
+    /// - `start_addr` points to valid memory.
+    /// - `size` is correct.
+    pub unsafe fn try_init(&self, start_addr: usize, size: usize) -> Result<(), InitError> {
+        if !start_addr.is_multiple_of(align_of::<usize>()) {
+            return Err(InitError::Misaligned);
+        }
+        self.with(|heap| heap.init(start_addr, size))
+    }
+
+    /// Initializes the heap with `memory`
+    ///
+    /// This is the safe version of [`init`](Self::init): the exclusive `'static` borrow
+    /// guarantees that the memory is valid and not used for anything else.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic in the same cases as [`init`](Self::init).
+    pub fn init_with(&self, memory: &'static mut [MaybeUninit<u8>]) {
+        // Safety: The memory is valid and borrowed by the heap forever.
+        unsafe { self.init(memory.as_mut_ptr() as usize, memory.len()) }
+    }
+
+    /// Adds another memory region to the heap
+    ///
+    /// This allows pooling several memory banks, which don't have to be contiguous,
+    /// into one heap.
+    ///
+    /// `start_addr` is the address where the region will be located.
+    ///
+    /// `size` is the size of the region in bytes.
+    ///
+    /// # Safety
+    ///
+    /// This function is safe if the following invariants hold:
+    ///
+    /// - `start_addr` points to valid memory.
+    /// - `size` is correct.
+    /// - the region does not overlap with memory already given to the heap.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if either of the following are true:
+    ///
+    /// - the heap has not been initialized with [`init`](Self::init).
+    /// - `size == 0`.
+    /// - `size`, after aligning start and end to `align_of::<usize>()`, is smaller than
+    ///   `2 * size_of::<usize>()`.
+    pub unsafe fn add_region(&self, start_addr: usize, size: usize) {
         assert!(size > 0);
-        critical_section::with(|cs| {
-            let mut heap = self.heap.borrow_ref_mut(cs);
-            assert!(!heap.1);
-            heap.1 = true;
-            heap.0.init(start_addr as *mut u8, size);
+        self.with(|heap| {
+            assert!(heap.initialized);
+            #[cfg(feature = "poison")]
+            poison::fill(start_addr as *mut u8, size, poison::FREED);
+            let Some((start, usable)) = heap.holes.add_region(start_addr, size) else {
+                panic!("Allocation too small for heap");
+            };
+            heap.bottom = heap.bottom.min(start);
+            heap.top = heap.top.max(start + usable);
+            heap.size += usable;
         });
     }
 
+    /// Frees all allocations at once, returning the heap to its state right after
+    /// [`init`](Self::init).
+    ///
+    /// Regions added with [`add_region`](Self::add_region) are no longer used, the
+    /// statistics start over and the interrupt cache is emptied. The out-of-memory and
+    /// guard handlers and the canary configuration are kept. An uninitialized heap is
+    /// left as it is.
+    ///
+    /// # Safety
+    ///
+    /// No memory allocated from the heap before the reset may be used afterwards.
+    pub unsafe fn reset(&self) {
+        self.with(|heap| heap.reset());
+        #[cfg(feature = "isr_cache")]
+        self.cache.drain(|_, _| {});
+    }
+
+    /// Returns the heap to its state right after [`init`](Self::init), if no allocation
+    /// is live.
+    ///
+    /// This is the safe version of [`reset`](Self::reset): it fails with the number of
+    /// live allocations instead. Blocks held by the interrupt cache count as live, so
+    /// [`drain_cache`](Self::drain_cache) has to be called first.
+    pub fn try_reset(&self) -> Result<(), ResetError> {
+        self.with(|heap| {
+            if heap.live != 0 {
+                return Err(ResetError {
+                    live_allocations: heap.live,
+                });
+            }
+            // Safety: Without live allocations no memory of the heap is in use.
+            unsafe { heap.reset() };
+            Ok(())
+        })
+    }
+
     /// Returns an estimate of the amount of bytes in use.
     pub fn used(&self) -> usize {
-        critical_section::with(|cs| self.heap.borrow_ref_mut(cs).0.used())
+        self.with(|heap| heap.used)
     }
 
     /// Returns an estimate of the amount of bytes available.
     pub fn free(&self) -> usize {
-        critical_section::with(|cs| self.heap.borrow_ref_mut(cs).0.free())
+        self.with(|heap| heap.size - heap.used)
     }
 
-    fn alloc(&self, layout: Layout) -> Option<NonNull<u8>> {
-        critical_section::with(|cs| {
-            self.heap
-                .borrow_ref_mut(cs)
-                .0
-                .allocate_first_fit(layout)
-                .ok()
+    /// Returns the size of the largest free block in bytes.
+    ///
+    /// This is the largest allocation that can currently succeed, unless it requires an
+    /// alignment above `align_of::<usize>()`.
+    pub fn largest_free_block(&self) -> usize {
+        self.with(|heap| heap.holes.iter().map(|(_, size)| size).max().unwrap_or(0))
+    }
+
+    /// Returns whether an allocation with `layout` would currently succeed.
+    ///
+    /// The answer is only valid until the next allocation or deallocation.
+    pub fn can_allocate(&self, layout: Layout) -> bool {
+        self.with(|heap| {
+            #[cfg(feature = "isr_cache")]
+            let layout = cache::block_layout(layout);
+            #[cfg(feature = "guard")]
+            let Some(layout) = heap.guard.outer_layout(layout) else {
+                return false;
+            };
+            heap.holes.can_allocate(layout)
+        })
+    }
+
+    /// Returns how fragmented the free memory is.
+    pub fn fragmentation(&self) -> Fragmentation {
+        self.with(|heap| heap.holes.iter().map(|(_, size)| size).collect())
+    }
+
+    /// Checks the free list of the heap for corruption.
+    ///
+    /// Every free block is checked for a valid address and size, for overlapping or
+    /// unmerged neighbours, and the free blocks and allocations have to add up to the
+    /// size of the heap. The first invalid block is reported.
+    ///
+    /// Free block headers are only followed if they lie inside the heap memory. With
+    /// several regions only the address range spanning all of them is known, so a
+    /// corrupted pointer into a gap between regions is followed.
+    ///
+    /// This walks all free blocks with the heap locked.
+    pub fn check(&self) -> Result<(), HeapCorruption> {
+        self.with(|heap| {
+            let contains = |addr: usize, size: usize| {
+                addr >= heap.bottom && addr.checked_add(size).is_some_and(|end| end <= heap.top)
+            };
+            let free = heap.holes.check(contains)?;
+            if free + heap.used != heap.size {
+                return Err(HeapCorruption {
+                    address: heap.bottom,
+                    kind: CorruptionKind::SizeMismatch,
+                });
+            }
+            Ok(())
+        })
+    }
+
+    /// Allocates memory for `layout` without waiting for the heap lock.
+    ///
+    /// If the heap is locked, for example by the code an interrupt handler preempted,
+    /// this fails with [`TryAllocError::Contended`] instead of waiting. A failed
+    /// allocation is [`TryAllocError::Exhausted`], the out-of-memory handler isn't called.
+    pub fn try_alloc(&self, layout: Layout) -> Result<NonNull<u8>, TryAllocError> {
+        self.try_with(|heap| {
+            let allocation = heap.allocate(layout);
+            #[cfg(feature = "stats")]
+            heap.stats.record_alloc(layout, allocation.is_some());
+            allocation
+        })
+        .ok_or(TryAllocError::Contended)?
+        .ok_or(TryAllocError::Exhausted)
+    }
+
+    /// Frees memory without waiting for the heap lock.
+    ///
+    /// If the heap is locked this fails with [`TryAllocError::Contended`] and the memory
+    /// stays allocated, so it can be freed again later.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been allocated by this heap with `layout` and not freed since.
+    pub unsafe fn try_dealloc(
+        &self,
+        ptr: NonNull<u8>,
+        layout: Layout,
+    ) -> Result<(), TryAllocError> {
+        let intact = self
+            .try_with(|heap| {
+                #[cfg(feature = "stats")]
+                heap.stats.record_dealloc(layout);
+                heap.deallocate(ptr, layout)
+            })
+            .ok_or(TryAllocError::Contended)?;
+        if !intact {
+            self.report_overwritten(ptr.as_ptr() as usize, layout);
+        }
+        Ok(())
+    }
+
+    /// Allocates memory for `layout` from the interrupt cache, without locking the heap.
+    ///
+    /// Allocations of up to 128 bytes with an alignment of at most `align_of::<usize>()`
+    /// are served from a few cached blocks per size class. This returns `None` for larger
+    /// layouts and once the class runs out of blocks, until
+    /// [`refill_cache`](Self::refill_cache) is called. The memory can be freed with
+    /// [`dealloc_cached`](Self::dealloc_cached) or like any other allocation.
+    #[cfg(feature = "isr_cache")]
+    pub fn alloc_cached(&self, layout: Layout) -> Option<NonNull<u8>> {
+        self.cache.take(layout)
+    }
+
+    /// Frees memory into the interrupt cache, without locking the heap.
+    ///
+    /// Returns `false` if `layout` has no size class or its slots are full. The memory
+    /// then stays allocated, so it can be freed again later.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been allocated by this heap with `layout` and not freed since.
+    #[cfg(feature = "isr_cache")]
+    pub unsafe fn dealloc_cached(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        self.cache.put(ptr, layout)
+    }
+
+    /// Fills the empty slots of the interrupt cache with blocks from the heap.
+    ///
+    /// Call this outside of interrupt handlers, for example from the idle loop.
+    #[cfg(feature = "isr_cache")]
+    pub fn refill_cache(&self) {
+        self.with(|heap| {
+            for layout in self.cache.vacancies() {
+                let Some(block) = heap.allocate(layout) else {
+                    return;
+                };
+                #[cfg(feature = "stats")]
+                heap.stats.record_alloc(layout, true);
+                if !self.cache.put(block, layout) {
+                    // An interrupt handler filled the slot in the meantime.
+                    // Safety: The block was just allocated with `layout`.
+                    unsafe { heap.deallocate(block, layout) };
+                    #[cfg(feature = "stats")]
+                    heap.stats.record_dealloc(layout);
+                    return;
+                }
+            }
         })
     }
 
+    /// Returns the blocks of the interrupt cache to the heap.
+    #[cfg(feature = "isr_cache")]
+    pub fn drain_cache(&self) {
+        let overwritten = self.with(|heap| {
+            let mut overwritten = None;
+            self.cache.drain(|block, layout| {
+                #[cfg(feature = "stats")]
+                heap.stats.record_dealloc(layout);
+                // Safety: Cached blocks are allocated with the layout of their class.
+                if !unsafe { heap.deallocate(block, layout) } {
+                    overwritten = Some((block.as_ptr() as usize, layout));
+                }
+            });
+            overwritten
+        });
+        if let Some((address, layout)) = overwritten {
+            self.report_overwritten(address, layout);
+        }
+    }
+
+    /// Returns the allocation statistics of the heap.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> HeapStats {
+        self.with(|heap| heap.stats)
+    }
+
+    /// Sets a function that is called when an allocation fails.
+    ///
+    /// The handler runs with the heap unlocked, so it may log the failure or
+    /// free memory, for example by dropping caches. If it returns [`OomAction::Retry`]
+    /// the allocation is attempted again, so it must eventually return
+    /// [`OomAction::Fail`] if no more memory can be freed.
+    #[cfg(feature = "stats")]
+    pub fn set_oom_handler(&self, handler: OomHandler) {
+        self.with(|heap| heap.oom_handler = Some(handler));
+    }
+
+    /// Sets the number of canary words in front of and behind every allocation.
+    ///
+    /// The canaries are checked when an allocation is freed and by
+    /// [`verify_all`](Self::verify_all). Leading canaries also protect the header that
+    /// keeps track of the allocation. The default is one word on each side.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if any allocation is live.
+    #[cfg(feature = "guard")]
+    pub fn set_canaries(&self, leading: usize, trailing: usize) {
+        self.with(|heap| heap.guard.set_canaries(leading, trailing));
+    }
+
+    /// Sets a function that is called when overwritten canaries are found.
+    ///
+    /// The handler runs with the heap unlocked. Without a handler the heap
+    /// panics. The damaged allocation is not returned to the heap.
+    #[cfg(feature = "guard")]
+    pub fn set_guard_handler(&self, handler: GuardHandler) {
+        self.with(|heap| heap.guard.handler = Some(handler));
+    }
+
+    /// Checks the canaries of all live allocations.
+    ///
+    /// The first allocation with overwritten canaries is reported to the guard handler
+    /// and `false` is returned.
+    #[cfg(feature = "guard")]
+    pub fn verify_all(&self) -> bool {
+        let corrupted = self.with(|heap| heap.guard.find_corrupted());
+        match corrupted {
+            Some((address, layout)) => {
+                self.report_overwritten(address, layout);
+                false
+            }
+            None => true,
+        }
+    }
+
+    /// Calls the guard handler for an allocation with overwritten canaries.
+    ///
+    /// Allocations that fit a cache class are reported with the layout of the class,
+    /// which is the one their canaries were placed for.
+    #[cfg(feature = "guard")]
+    fn report_overwritten(&self, address: usize, layout: Layout) {
+        #[cfg(feature = "isr_cache")]
+        let layout = cache::block_layout(layout);
+        let handler = self.with(|heap| heap.guard.handler);
+        guard::report(handler, address, layout);
+    }
+
+    #[cfg(not(feature = "guard"))]
+    fn report_overwritten(&self, _address: usize, _layout: Layout) {}
+
+    fn alloc(&self, layout: Layout) -> Option<NonNull<u8>> {
+        loop {
+            let allocation = self.with(|heap| {
+                let allocation = heap.allocate(layout);
+                #[cfg(feature = "stats")]
+                heap.stats.record_alloc(layout, allocation.is_some());
+                allocation
+            });
+            if allocation.is_some() || !self.retry_after_oom(layout) {
+                return allocation;
+            }
+        }
+    }
+
+    /// Calls the out-of-memory handler and returns whether to retry the allocation.
+    #[cfg(feature = "stats")]
+    fn retry_after_oom(&self, layout: Layout) -> bool {
+        let (handler, stats) = self.with(|heap| (heap.oom_handler, heap.stats));
+        handler.is_some_and(|handler| handler(layout, &stats) == OomAction::Retry)
+    }
+
+    #[cfg(not(feature = "stats"))]
+    fn retry_after_oom(&self, _layout: Layout) -> bool {
+        false
+    }
+
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        critical_section::with(|cs| {
-            self.heap
-                .borrow_ref_mut(cs)
-                .0
-                .deallocate(NonNull::new_unchecked(ptr), layout)
+        let intact = self.with(|heap| {
+            #[cfg(feature = "stats")]
+            heap.stats.record_dealloc(layout);
+            heap.deallocate(NonNull::new_unchecked(ptr), layout)
         });
+        if !intact {
+            self.report_overwritten(ptr as usize, layout);
+        }
+    }
+
+    /// Resizes the allocation at `ptr` to `new_size` bytes, keeping its alignment.
+    ///
+    /// The block is grown or shrunk in place if the neighbouring memory allows it.
+    /// Otherwise a new block is allocated, the contents are copied over and the old
+    /// block is freed. On failure the old block is left untouched.
+    ///
+    /// Guarded allocations are always moved, as their canaries sit behind the data, and
+    /// so are poisoned ones, to check the memory they grow into.
+    unsafe fn realloc(
+        &self,
+        ptr: NonNull<u8>,
+        layout: Layout,
+        new_size: usize,
+    ) -> Option<NonNull<u8>> {
+        let new_layout = Layout::from_size_align(new_size, layout.align()).ok()?;
+        if !cfg!(any(feature = "guard", feature = "poison"))
+            && self.resize_in_place(ptr, layout, new_layout)
+        {
+            return Some(ptr);
+        }
+
+        // The copy happens with the heap unlocked to keep interrupt latency low.
+        let allocation = self.alloc(new_layout)?;
+        ptr::copy_nonoverlapping(
+            ptr.as_ptr(),
+            allocation.as_ptr(),
+            layout.size().min(new_size),
+        );
+        self.dealloc(ptr.as_ptr(), layout);
+        Some(allocation)
+    }
+
+    /// Grows or shrinks the allocation at `ptr` without moving it, if possible.
+    unsafe fn resize_in_place(&self, ptr: NonNull<u8>, layout: Layout, new_layout: Layout) -> bool {
+        self.with(|heap| {
+            let block_size = |layout| {
+                #[cfg(feature = "isr_cache")]
+                let layout = cache::block_layout(layout);
+                HoleList::block_size(layout)
+            };
+            let old_block = block_size(layout);
+            let new_block = block_size(new_layout);
+            let resized = heap.holes.resize_in_place(ptr, old_block, new_block);
+            if resized {
+                heap.used = heap.used - old_block + new_block;
+                #[cfg(feature = "stats")]
+                heap.stats.record_realloc(layout, new_layout, true);
+            }
+            resized
+        })
+    }
+}
+
+impl<R: RawMutex> crate::Heap for Heap<R> {
+    unsafe fn init(&self, start_addr: usize, size: usize) {
+        self.init(start_addr, size);
+    }
+
+    unsafe fn try_init(&self, start_addr: usize, size: usize) -> Result<(), InitError> {
+        self.try_init(start_addr, size)
+    }
+
+    fn used(&self) -> usize {
+        self.used()
+    }
+
+    fn free(&self) -> usize {
+        self.free()
+    }
+}
+
+impl<R: RawMutex> Introspect for Heap<R> {
+    fn largest_free_block(&self) -> usize {
+        self.largest_free_block()
+    }
+
+    fn can_allocate(&self, layout: Layout) -> bool {
+        self.can_allocate(layout)
+    }
+
+    fn fragmentation(&self) -> Fragmentation {
+        self.fragmentation()
+    }
+
+    fn check(&self) -> Result<(), HeapCorruption> {
+        self.check()
     }
 }
 
-unsafe impl GlobalAlloc for Heap {
+#[cfg(feature = "stats")]
+impl<R: RawMutex> Stats for Heap<R> {
+    fn stats(&self) -> HeapStats {
+        self.stats()
+    }
+}
+
+unsafe impl<R: RawMutex> GlobalAlloc for Heap<R> {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
         self.alloc(layout)
             .map_or(ptr::null_mut(), |allocation| allocation.as_ptr())
@@ -101,14 +719,20 @@ unsafe impl GlobalAlloc for Heap {
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
         self.dealloc(ptr, layout);
     }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        self.realloc(NonNull::new_unchecked(ptr), layout, new_size)
+            .map_or(ptr::null_mut(), |allocation| allocation.as_ptr())
+    }
 }
 
 #[cfg(feature = "allocator_api")]
 mod allocator_api {
     use super::*;
+    use crate::allocator;
     use core::alloc::{AllocError, Allocator};
 
-    unsafe impl Allocator for Heap {
+    unsafe impl<R: RawMutex> Allocator for Heap<R> {
         fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
             match layout.size() {
                 0 => Ok(NonNull::slice_from_raw_parts(layout.dangling_ptr(), 0)),
@@ -123,5 +747,38 @@ mod allocator_api {
                 self.dealloc(ptr.as_ptr(), layout);
             }
         }
+
+        unsafe fn grow(
+            &self,
+            ptr: NonNull<u8>,
+            old_layout: Layout,
+            new_layout: Layout,
+        ) -> Result<NonNull<[u8]>, AllocError> {
+            allocator::resize(self, ptr, old_layout, new_layout, |ptr, layout, size| {
+                self.realloc(ptr, layout, size)
+            })
+        }
+
+        unsafe fn grow_zeroed(
+            &self,
+            ptr: NonNull<u8>,
+            old_layout: Layout,
+            new_layout: Layout,
+        ) -> Result<NonNull<[u8]>, AllocError> {
+            allocator::resize_zeroed(self, ptr, old_layout, new_layout, |ptr, layout, size| {
+                self.realloc(ptr, layout, size)
+            })
+        }
+
+        unsafe fn shrink(
+            &self,
+            ptr: NonNull<u8>,
+            old_layout: Layout,
+            new_layout: Layout,
+        ) -> Result<NonNull<[u8]>, AllocError> {
+            allocator::resize(self, ptr, old_layout, new_layout, |ptr, layout, size| {
+                self.realloc(ptr, layout, size)
+            })
+        }
     }
 }
@@ -0,0 +1,504 @@
+use core::alloc::Layout;
+use core::mem::{align_of, size_of};
+use core::ptr::{self, NonNull};
+
+#[cfg(feature = "poison")]
+use crate::poison;
+use crate::{CorruptionKind, HeapCorruption};
+
+/// Header stored at the start of every free block.
+struct Hole {
+    size: usize,
+    next: Option<NonNull<Hole>>,
+}
+
+/// The smallest block that can be tracked. Smaller allocations are rounded up to this.
+pub(super) const MIN_SIZE: usize = size_of::<Hole>();
+
+/// All block addresses and sizes are multiples of this.
+pub(super) const ALIGN: usize = align_of::<Hole>();
+
+/// A list of free memory blocks, sorted by address.
+///
+/// The list nodes live inside the free blocks themselves. Neighbouring free blocks are
+/// merged when memory is returned, so no two holes in the list are adjacent.
+///
+/// With the `poison` feature, headers that stop being part of the list are overwritten
+/// with the free pattern, so free memory only holds the pattern apart from the header
+/// at the start of each hole.
+pub(super) struct HoleList {
+    head: Option<NonNull<Hole>>,
+}
+
+impl HoleList {
+    pub(super) const fn empty() -> HoleList {
+        HoleList { head: None }
+    }
+
+    /// Returns the number of bytes a block for `layout` occupies.
+    pub(super) fn block_size(layout: Layout) -> usize {
+        // `Layout` guarantees that `size` rounded up to `align` does not overflow `isize`,
+        // so rounding up to `ALIGN` cannot overflow `usize`.
+        align_up(layout.size().max(MIN_SIZE), ALIGN)
+    }
+
+    /// Adds the memory range `[start_addr, start_addr + size)` to the list.
+    ///
+    /// The range is shrunk to `ALIGN` on both ends. Returns the start address and size of
+    /// the usable range, or `None` (leaving the list untouched) if less than `MIN_SIZE`
+    /// remain.
+    ///
+    /// # Safety
+    ///
+    /// The memory range must be valid, unused and not already part of the list.
+    pub(super) unsafe fn add_region(
+        &mut self,
+        start_addr: usize,
+        size: usize,
+    ) -> Option<(usize, usize)> {
+        let size = Self::usable(start_addr, size);
+        if size < MIN_SIZE {
+            return None;
+        }
+        let start = start_addr.next_multiple_of(ALIGN);
+        self.insert(start as *mut u8, size);
+        Some((start, size))
+    }
+
+    /// Returns the size of the range at `start_addr` after shrinking it to `ALIGN` on
+    /// both ends.
+    pub(super) fn usable(start_addr: usize, size: usize) -> usize {
+        start_addr
+            .checked_next_multiple_of(ALIGN)
+            .map_or(0, |start| {
+                size.saturating_sub(start - start_addr) & !(ALIGN - 1)
+            })
+    }
+
+    /// Allocates a block for `layout` from the first hole it fits in.
+    pub(super) fn allocate_first_fit(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        let size = Self::block_size(layout);
+        let mut prev = None;
+        let mut cursor = self.head;
+        while let Some(hole) = cursor {
+            // Safety: all list nodes point to valid holes.
+            let (hole_size, next) = unsafe { (hole.as_ref().size, hole.as_ref().next) };
+            let hole_addr = hole.as_ptr() as usize;
+            if let Some(start) = fit(hole_addr, hole_size, size, layout.align()) {
+                // Safety: `fit` guarantees that the block lies within the hole.
+                unsafe {
+                    let base = hole.as_ptr().cast::<u8>();
+                    let block = base.add(start - hole_addr);
+                    let end = block.add(size);
+                    let back_size = hole_size - (start - hole_addr) - size;
+                    let after = if back_size > 0 {
+                        Some(write_hole(end, back_size, next))
+                    } else {
+                        next
+                    };
+                    if start > hole_addr {
+                        (*hole.as_ptr()).size = start - hole_addr;
+                        (*hole.as_ptr()).next = after;
+                    } else {
+                        self.link(prev, after);
+                        #[cfg(feature = "poison")]
+                        poison::fill(base, MIN_SIZE, poison::FREED);
+                    }
+                    return Some(NonNull::new_unchecked(block));
+                }
+            }
+            prev = Some(hole);
+            cursor = next;
+        }
+        None
+    }
+
+    /// Returns whether [`allocate_first_fit`](Self::allocate_first_fit) would succeed.
+    pub(super) fn can_allocate(&self, layout: Layout) -> bool {
+        let size = Self::block_size(layout);
+        self.iter()
+            .any(|(addr, hole_size)| fit(addr, hole_size, size, layout.align()).is_some())
+    }
+
+    /// Returns the address and size of every hole, in address order.
+    pub(super) fn iter(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let mut cursor = self.head;
+        core::iter::from_fn(move || {
+            let hole = cursor?;
+            // Safety: all list nodes point to valid holes.
+            let Hole { size, next } = unsafe { hole.as_ptr().read() };
+            cursor = next;
+            Some((hole.as_ptr() as usize, size))
+        })
+    }
+
+    /// Walks the list and validates every hole, returning the total free size.
+    ///
+    /// `contains(addr, size)` must return whether the range lies inside the heap memory.
+    /// Every header is checked against it before it is read, so a corrupted `next`
+    /// pointer is reported instead of followed.
+    pub(super) fn check(
+        &self,
+        contains: impl Fn(usize, usize) -> bool,
+    ) -> Result<usize, HeapCorruption> {
+        let mut prev_end = None;
+        let mut total = 0;
+        let mut cursor = self.head;
+        while let Some(hole) = cursor {
+            let address = hole.as_ptr() as usize;
+            let corruption = |kind| Err(HeapCorruption { address, kind });
+            if !address.is_multiple_of(ALIGN) {
+                return corruption(CorruptionKind::Misaligned);
+            }
+            if !contains(address, MIN_SIZE) {
+                return corruption(CorruptionKind::OutOfBounds);
+            }
+            // Safety: The header lies inside the heap memory.
+            let Hole { size, next } = unsafe { hole.as_ptr().read() };
+            if size < MIN_SIZE || !size.is_multiple_of(ALIGN) {
+                return corruption(CorruptionKind::InvalidSize);
+            }
+            if !contains(address, size) {
+                return corruption(CorruptionKind::OutOfBounds);
+            }
+            // Holes are sorted and never adjacent, which also rules out cycles.
+            match prev_end {
+                Some(end) if address < end => return corruption(CorruptionKind::Overlap),
+                Some(end) if address == end => return corruption(CorruptionKind::NotMerged),
+                _ => {}
+            }
+            prev_end = Some(address + size);
+            total += size;
+            cursor = next;
+        }
+        Ok(total)
+    }
+
+    /// Returns the block at `ptr` to the list, merging it with its neighbours.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by [`allocate_first_fit`](Self::allocate_first_fit)
+    /// for a layout with the same block size as `layout`.
+    pub(super) unsafe fn deallocate(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        self.insert(ptr.as_ptr(), Self::block_size(layout));
+    }
+
+    /// Tries to resize the block at `ptr` from `old_size` to `new_size` bytes without moving it.
+    ///
+    /// Both sizes are block sizes as returned by [`block_size`](Self::block_size).
+    /// Growing takes memory from a hole directly after the block. Shrinking gives the tail
+    /// back to the list, as long as it can be merged with a following hole or is large
+    /// enough to form a hole of its own. Returns `false` and leaves the list untouched if
+    /// neither is possible.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a live block of `old_size` bytes handed out by this list.
+    pub(super) unsafe fn resize_in_place(
+        &mut self,
+        ptr: NonNull<u8>,
+        old_size: usize,
+        new_size: usize,
+    ) -> bool {
+        if new_size == old_size {
+            return true;
+        }
+        let old_end = ptr.as_ptr() as usize + old_size;
+        let (prev, next) = self.neighbours(old_end);
+        let adjacent = next.filter(|hole| hole.as_ptr() as usize == old_end);
+        if new_size > old_size {
+            let Some(hole) = adjacent else {
+                return false;
+            };
+            let Hole { size, next } = hole.as_ptr().read();
+            let rest = match size.checked_sub(new_size - old_size) {
+                Some(rest) if rest == 0 || rest >= MIN_SIZE => rest,
+                _ => return false,
+            };
+            if rest == 0 {
+                self.link(prev, next);
+            } else {
+                let moved = write_hole(ptr.as_ptr().add(new_size), rest, next);
+                self.link(prev, Some(moved));
+            }
+        } else {
+            let tail = ptr.as_ptr().add(new_size);
+            let shrink_by = old_size - new_size;
+            let hole = match adjacent {
+                Some(hole) => {
+                    let Hole { size, next } = hole.as_ptr().read();
+                    write_hole(tail, shrink_by + size, next)
+                }
+                None if shrink_by >= MIN_SIZE => write_hole(tail, shrink_by, next),
+                None => return false,
+            };
+            self.link(prev, Some(hole));
+        }
+        true
+    }
+
+    /// Inserts the free block `[ptr, ptr + size)`, merging it with its neighbours.
+    unsafe fn insert(&mut self, ptr: *mut u8, size: usize) {
+        let addr = ptr as usize;
+        let (prev, next) = self.neighbours(addr);
+        let (mut size, mut after) = (size, next);
+        if let Some(hole) = next {
+            if addr + size == hole.as_ptr() as usize {
+                size += hole.as_ref().size;
+                after = hole.as_ref().next;
+                #[cfg(feature = "poison")]
+                poison::fill(hole.as_ptr().cast(), MIN_SIZE, poison::FREED);
+            }
+        }
+        match prev {
+            Some(hole) if hole.as_ptr() as usize + hole.as_ref().size == addr => {
+                (*hole.as_ptr()).size += size;
+                (*hole.as_ptr()).next = after;
+            }
+            _ => {
+                let hole = write_hole(ptr, size, after);
+                self.link(prev, Some(hole));
+            }
+        }
+    }
+
+    /// Returns the last hole below `addr` and the first hole at or above it.
+    fn neighbours(&self, addr: usize) -> (Option<NonNull<Hole>>, Option<NonNull<Hole>>) {
+        let mut prev = None;
+        let mut cursor = self.head;
+        while let Some(hole) = cursor {
+            if hole.as_ptr() as usize >= addr {
+                break;
+            }
+            prev = Some(hole);
+            // Safety: all list nodes point to valid holes.
+            cursor = unsafe { hole.as_ref().next };
+        }
+        (prev, cursor)
+    }
+
+    /// Makes `next` follow `prev`, or become the head if there is no `prev`.
+    unsafe fn link(&mut self, prev: Option<NonNull<Hole>>, next: Option<NonNull<Hole>>) {
+        match prev {
+            Some(hole) => (*hole.as_ptr()).next = next,
+            None => self.head = next,
+        }
+    }
+}
+
+/// Writes a hole header to `ptr`.
+unsafe fn write_hole(ptr: *mut u8, size: usize, next: Option<NonNull<Hole>>) -> NonNull<Hole> {
+    let hole = ptr.cast::<Hole>();
+    ptr::write(hole, Hole { size, next });
+    NonNull::new_unchecked(hole)
+}
+
+/// Returns the start address of a `size` byte block aligned to `align` inside the given
+/// hole, if there is one.
+///
+/// Padding in front of or behind the block has to be large enough to remain a hole.
+fn fit(hole_addr: usize, hole_size: usize, size: usize, align: usize) -> Option<usize> {
+    let mut start = checked_align_up(hole_addr, align)?;
+    if start != hole_addr && start - hole_addr < MIN_SIZE {
+        start = checked_align_up(hole_addr + MIN_SIZE, align)?;
+    }
+    let back_size = (hole_addr + hole_size).checked_sub(start.checked_add(size)?)?;
+    if back_size != 0 && back_size < MIN_SIZE {
+        return None;
+    }
+    Some(start)
+}
+
+fn align_up(value: usize, align: usize) -> usize {
+    (value + align - 1) & !(align - 1)
+}
+
+fn checked_align_up(value: usize, align: usize) -> Option<usize> {
+    Some(value.checked_add(align - 1)? & !(align - 1))
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec::Vec;
+
+    #[repr(align(64))]
+    struct Memory([u8; 256]);
+
+    impl Memory {
+        fn new() -> Memory {
+            Memory([0; 256])
+        }
+
+        fn addr(&mut self) -> usize {
+            self.0.as_mut_ptr() as usize
+        }
+    }
+
+    /// Returns the offset from `base` and the size of every hole.
+    fn holes(list: &HoleList, base: usize) -> Vec<(usize, usize)> {
+        let mut holes = Vec::new();
+        let mut cursor = list.head;
+        while let Some(hole) = cursor {
+            // Safety: The tests only build valid lists.
+            let hole_ref = unsafe { hole.as_ref() };
+            holes.push((hole.as_ptr() as usize - base, hole_ref.size));
+            cursor = hole_ref.next;
+        }
+        holes
+    }
+
+    fn layout(size: usize, align: usize) -> Layout {
+        Layout::from_size_align(size, align).unwrap()
+    }
+
+    #[test]
+    fn add_region_aligns_both_ends() {
+        let mut memory = Memory::new();
+        let base = memory.addr();
+        let mut list = HoleList::empty();
+        assert_eq!(
+            unsafe { list.add_region(base + 1, 100) },
+            Some((base + ALIGN, 88))
+        );
+        assert_eq!(holes(&list, base), [(ALIGN, 88)]);
+    }
+
+    #[test]
+    fn add_region_too_small() {
+        let mut memory = Memory::new();
+        let base = memory.addr();
+        let mut list = HoleList::empty();
+        assert_eq!(unsafe { list.add_region(base + 1, MIN_SIZE) }, None);
+        assert_eq!(holes(&list, base), []);
+    }
+
+    #[test]
+    fn small_allocations_are_rounded_up() {
+        assert_eq!(HoleList::block_size(layout(1, 1)), MIN_SIZE);
+        assert_eq!(
+            HoleList::block_size(layout(MIN_SIZE + 1, 1)),
+            MIN_SIZE + ALIGN
+        );
+    }
+
+    #[test]
+    fn allocate_splits_the_first_hole() {
+        let mut memory = Memory::new();
+        let base = memory.addr();
+        let mut list = HoleList::empty();
+        unsafe { list.add_region(base, 256) };
+        let block = list.allocate_first_fit(layout(32, 8)).unwrap();
+        assert_eq!(block.as_ptr() as usize, base);
+        assert_eq!(holes(&list, base), [(32, 224)]);
+        assert_eq!(list.allocate_first_fit(layout(256, 8)), None);
+    }
+
+    #[test]
+    fn aligned_allocation_leaves_a_hole_in_front() {
+        let mut memory = Memory::new();
+        let base = memory.addr();
+        let mut list = HoleList::empty();
+        unsafe { list.add_region(base, 256) };
+        let block = list.allocate_first_fit(layout(32, 64)).unwrap();
+        assert_eq!(block.as_ptr() as usize, base);
+        let block = list.allocate_first_fit(layout(32, 64)).unwrap();
+        assert_eq!(block.as_ptr() as usize, base + 64);
+        assert_eq!(holes(&list, base), [(32, 32), (96, 160)]);
+    }
+
+    #[test]
+    fn padding_too_small_for_a_hole_is_skipped() {
+        let mut memory = Memory::new();
+        let base = memory.addr();
+        let mut list = HoleList::empty();
+        unsafe { list.add_region(base + ALIGN, 256 - ALIGN) };
+        // An aligned block at `base + 2 * ALIGN` would leave a gap smaller than a hole.
+        let block = list.allocate_first_fit(layout(8, 2 * ALIGN)).unwrap();
+        let offset = block.as_ptr() as usize - base;
+        assert!(offset - ALIGN >= MIN_SIZE);
+        assert_eq!(holes(&list, base)[0], (ALIGN, offset - ALIGN));
+    }
+
+    #[test]
+    fn deallocate_merges_with_both_neighbours() {
+        let mut memory = Memory::new();
+        let base = memory.addr();
+        let mut list = HoleList::empty();
+        unsafe { list.add_region(base, 256) };
+        let blocks: Vec<_> = (0..4)
+            .map(|_| list.allocate_first_fit(layout(32, 8)).unwrap())
+            .collect();
+        unsafe {
+            list.deallocate(blocks[0], layout(32, 8));
+            list.deallocate(blocks[2], layout(32, 8));
+            assert_eq!(holes(&list, base), [(0, 32), (64, 32), (128, 128)]);
+            list.deallocate(blocks[1], layout(32, 8));
+            assert_eq!(holes(&list, base), [(0, 96), (128, 128)]);
+            list.deallocate(blocks[3], layout(32, 8));
+        }
+        assert_eq!(holes(&list, base), [(0, 256)]);
+    }
+
+    #[test]
+    fn regions_are_merged_only_if_adjacent() {
+        let mut memory = Memory::new();
+        let base = memory.addr();
+        let mut list = HoleList::empty();
+        unsafe {
+            list.add_region(base + 128, 64);
+            list.add_region(base, 64);
+            assert_eq!(holes(&list, base), [(0, 64), (128, 64)]);
+            list.add_region(base + 64, 64);
+        }
+        assert_eq!(holes(&list, base), [(0, 192)]);
+        assert_eq!(list.allocate_first_fit(layout(256, 8)), None);
+    }
+
+    #[test]
+    fn grow_in_place_takes_from_the_next_hole() {
+        let mut memory = Memory::new();
+        let base = memory.addr();
+        let mut list = HoleList::empty();
+        unsafe { list.add_region(base, 256) };
+        let block = list.allocate_first_fit(layout(32, 8)).unwrap();
+        assert!(unsafe { list.resize_in_place(block, 32, 64) });
+        assert_eq!(holes(&list, base), [(64, 192)]);
+        assert!(unsafe { list.resize_in_place(block, 64, 256) });
+        assert_eq!(holes(&list, base), []);
+    }
+
+    #[test]
+    fn grow_in_place_fails_without_a_free_neighbour() {
+        let mut memory = Memory::new();
+        let base = memory.addr();
+        let mut list = HoleList::empty();
+        unsafe { list.add_region(base, 256) };
+        let block = list.allocate_first_fit(layout(32, 8)).unwrap();
+        list.allocate_first_fit(layout(32, 8)).unwrap();
+        assert!(!unsafe { list.resize_in_place(block, 32, 64) });
+        assert_eq!(holes(&list, base), [(64, 192)]);
+    }
+
+    #[test]
+    fn shrink_in_place_returns_the_tail() {
+        let mut memory = Memory::new();
+        let base = memory.addr();
+        let mut list = HoleList::empty();
+        unsafe { list.add_region(base, 256) };
+        let blocks: Vec<_> = (0..3)
+            .map(|_| list.allocate_first_fit(layout(64, 8)).unwrap())
+            .collect();
+        unsafe {
+            assert!(list.resize_in_place(blocks[0], 64, 32));
+            assert_eq!(holes(&list, base), [(32, 32), (192, 64)]);
+            assert!(list.resize_in_place(blocks[0], 32, 32 - ALIGN));
+            assert_eq!(holes(&list, base), [(32 - ALIGN, 32 + ALIGN), (192, 64)]);
+            // A tail smaller than a hole can only be returned next to a free block.
+            assert!(!list.resize_in_place(blocks[1], 64, 64 - ALIGN));
+        }
+    }
+}
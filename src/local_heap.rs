@@ -0,0 +1,120 @@
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+
+use crate::Heap;
+#[cfg(feature = "llff")]
+use crate::LlffHeapWith;
+#[cfg(any(feature = "llff", feature = "tlsf"))]
+use crate::RawMutex;
+#[cfg(feature = "tlsf")]
+use crate::TlsfHeapWith;
+
+/// A heap over a borrowed buffer, for scratch allocations inside a function.
+///
+/// The heap can't outlive its buffer, and neither can the collections allocated in it
+/// with the `allocator_api` feature, so no `unsafe` is needed:
+///
+/// ```rust
+/// #![feature(allocator_api)]
+/// use core::mem::MaybeUninit;
+/// use embedded_alloc::LocalTlsfHeap;
+///
+/// let mut buffer = [MaybeUninit::uninit(); 1024];
+/// let heap = LocalTlsfHeap::new(&mut buffer);
+/// let mut xs = Vec::new_in(&heap);
+/// xs.push(1);
+/// ```
+pub struct LocalHeap<'buf, H> {
+    heap: H,
+    buffer: PhantomData<&'buf mut [MaybeUninit<u8>]>,
+}
+
+impl<'buf, H: Heap> LocalHeap<'buf, H> {
+    /// Initializes `heap`, which must not be initialized yet, with `buffer`.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `heap` is initialized already or `buffer` is too small
+    /// for it.
+    pub fn with_heap(heap: H, buffer: &'buf mut [MaybeUninit<u8>]) -> Self {
+        // Safety: The buffer is borrowed for as long as the heap exists.
+        unsafe { heap.init(buffer.as_mut_ptr() as usize, buffer.len()) };
+        LocalHeap {
+            heap,
+            buffer: PhantomData,
+        }
+    }
+
+    /// Returns the heap, for example to check its usage.
+    pub fn heap(&self) -> &H {
+        &self.heap
+    }
+}
+
+#[cfg(feature = "llff")]
+impl<'buf, R: RawMutex> LocalHeap<'buf, LlffHeapWith<R>> {
+    /// Creates a linked list first fit heap over `buffer`.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `buffer` is too small for the heap.
+    pub fn new(buffer: &'buf mut [MaybeUninit<u8>]) -> Self {
+        Self::with_heap(LlffHeapWith::empty(), buffer)
+    }
+}
+
+#[cfg(feature = "tlsf")]
+impl<'buf, R: RawMutex> LocalHeap<'buf, TlsfHeapWith<R>> {
+    /// Creates a two-level segregated fit heap over `buffer`.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `buffer` is too small for the heap.
+    pub fn new(buffer: &'buf mut [MaybeUninit<u8>]) -> Self {
+        Self::with_heap(TlsfHeapWith::empty(), buffer)
+    }
+}
+
+#[cfg(feature = "allocator_api")]
+mod allocator_api {
+    use super::*;
+    use core::alloc::{AllocError, Allocator, Layout};
+    use core::ptr::NonNull;
+
+    unsafe impl<H: Allocator> Allocator for LocalHeap<'_, H> {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            self.heap.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.heap.deallocate(ptr, layout);
+        }
+
+        unsafe fn grow(
+            &self,
+            ptr: NonNull<u8>,
+            old_layout: Layout,
+            new_layout: Layout,
+        ) -> Result<NonNull<[u8]>, AllocError> {
+            self.heap.grow(ptr, old_layout, new_layout)
+        }
+
+        unsafe fn grow_zeroed(
+            &self,
+            ptr: NonNull<u8>,
+            old_layout: Layout,
+            new_layout: Layout,
+        ) -> Result<NonNull<[u8]>, AllocError> {
+            self.heap.grow_zeroed(ptr, old_layout, new_layout)
+        }
+
+        unsafe fn shrink(
+            &self,
+            ptr: NonNull<u8>,
+            old_layout: Layout,
+            new_layout: Layout,
+        ) -> Result<NonNull<[u8]>, AllocError> {
+            self.heap.shrink(ptr, old_layout, new_layout)
+        }
+    }
+}
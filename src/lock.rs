@@ -0,0 +1,57 @@
+/// A raw mutex that guards the state of a heap.
+///
+/// [`LlffHeap`](crate::LlffHeap) and [`TlsfHeap`](crate::TlsfHeap) use
+/// [`CriticalSectionRawMutex`], which disables interrupts while the heap is locked. Other
+/// locks, such as a hardware spinlock or a priority ceiling lock, can be used through
+/// [`LlffHeapWith`](crate::LlffHeapWith) and [`TlsfHeapWith`](crate::TlsfHeapWith).
+///
+/// Heaps still detect reentrant use of the same lock, such as an allocation from an
+/// interrupt handler that preempted a locked heap while the lock didn't mask it. This
+/// panics, so the lock has to keep such contexts out or they must use the non-blocking
+/// `try_alloc` and `try_dealloc`, which fail with [`TryAllocError::Contended`] instead.
+///
+/// # Safety
+///
+/// While [`lock`](Self::lock) or [`try_lock`](Self::try_lock) runs its closure, no other
+/// closure passed to the same mutex may run at the same time, except closures nested in
+/// it on the same thread of execution.
+pub unsafe trait RawMutex {
+    /// A mutex in the unlocked state.
+    const INIT: Self;
+
+    /// Runs `f` with the mutex locked, waiting until it is available.
+    fn lock<R>(&self, f: impl FnOnce() -> R) -> R;
+
+    /// Runs `f` with the mutex locked, or returns `None` if it isn't available right now.
+    fn try_lock<R>(&self, f: impl FnOnce() -> R) -> Option<R>;
+}
+
+/// A [`RawMutex`] that locks by entering a critical section.
+///
+/// An implementation of [`critical-section`](https://docs.rs/critical-section) must be
+/// provided.
+pub struct CriticalSectionRawMutex {
+    _private: (),
+}
+
+// Safety: Critical sections don't run concurrently with anything else.
+unsafe impl RawMutex for CriticalSectionRawMutex {
+    const INIT: Self = CriticalSectionRawMutex { _private: () };
+
+    fn lock<R>(&self, f: impl FnOnce() -> R) -> R {
+        critical_section::with(|_| f())
+    }
+
+    fn try_lock<R>(&self, f: impl FnOnce() -> R) -> Option<R> {
+        Some(self.lock(f))
+    }
+}
+
+/// The reason why a non-blocking allocation failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TryAllocError {
+    /// The heap was locked, so the operation gave up instead of waiting.
+    Contended,
+    /// The heap has no free block that fits the allocation.
+    Exhausted,
+}
@@ -0,0 +1,18 @@
+use core::alloc::Layout;
+
+use crate::HeapStats;
+
+/// What a heap does after its out-of-memory handler has returned
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OomAction {
+    /// Try the allocation again, for example after the handler freed some memory.
+    Retry,
+    /// Let the allocation fail.
+    Fail,
+}
+
+/// A function that is called when an allocation fails
+///
+/// It receives the layout of the failed allocation and the statistics of the heap,
+/// which already include the failure.
+pub type OomHandler = fn(Layout, &HeapStats) -> OomAction;
@@ -0,0 +1,29 @@
+/// Written to memory when it is allocated, to make reads of uninitialized memory stand out.
+pub(crate) const ALLOCATED: u8 = 0xA5;
+
+/// Written to memory when it is freed, and checked when it is allocated again.
+pub(crate) const FREED: u8 = 0xDD;
+
+/// Fills `len` bytes at `start` with `pattern`.
+///
+/// # Safety
+///
+/// The memory range must be valid for writes.
+pub(crate) unsafe fn fill(start: *mut u8, len: usize, pattern: u8) {
+    start.write_bytes(pattern, len);
+}
+
+/// Panics if any of the `len` bytes at `start` was modified since it was freed.
+///
+/// # Safety
+///
+/// The memory range must be valid for reads and initialized.
+pub(crate) unsafe fn check_freed(start: *const u8, len: usize) {
+    let bytes = core::slice::from_raw_parts(start, len);
+    if let Some(offset) = bytes.iter().position(|&byte| byte != FREED) {
+        panic!(
+            "Freed memory at {:#x} was modified",
+            start as usize + offset
+        );
+    }
+}
@@ -0,0 +1,428 @@
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::RefCell;
+use core::mem::{align_of, size_of};
+use core::ptr::{self, NonNull};
+
+use critical_section::Mutex;
+
+use crate::InitError;
+
+/// Header stored in every free block.
+struct FreeBlock {
+    next: Option<NonNull<FreeBlock>>,
+}
+
+/// A pool of `COUNT` blocks of `SIZE` bytes, followed by the pools in `Next`
+///
+/// Pools are chained to configure a [`PoolHeap`](crate::PoolHeap), from the smallest
+/// to the largest block size:
+///
+/// ```
+/// use embedded_alloc::{Pool, PoolHeap};
+///
+/// type Heap = PoolHeap<Pool<64, 1000, Pool<256, 100>>>;
+/// ```
+///
+/// `SIZE` must be a multiple of `size_of::<usize>()`. Blocks are aligned to the largest
+/// power of two that divides `SIZE`, so a pool of 64 byte blocks serves allocations
+/// with an alignment of up to 64.
+pub struct Pool<const SIZE: usize, const COUNT: usize, Next = ()> {
+    free: Option<NonNull<FreeBlock>>,
+    start: usize,
+    /// Number of blocks that have never been handed out, at the end of the pool.
+    fresh: usize,
+    used: usize,
+    next: Next,
+}
+
+mod sealed {
+    pub trait Sealed {}
+
+    impl Sealed for () {}
+    impl<const SIZE: usize, const COUNT: usize, Next> Sealed for super::Pool<SIZE, COUNT, Next> {}
+}
+
+/// A chain of [`Pool`]s, terminated by `()`
+///
+/// This trait is sealed and its methods are an implementation detail.
+pub trait Pools: sealed::Sealed {
+    /// The number of bytes `init` needs to carve out all pools, whatever the alignment
+    /// of the region.
+    const REGION_SIZE: usize;
+
+    #[doc(hidden)]
+    const EMPTY: Self;
+
+    /// Carves the pools out of `[start, end)`, returning the end of the used memory.
+    #[doc(hidden)]
+    unsafe fn init(&mut self, start: usize, end: usize) -> Option<usize>;
+
+    #[doc(hidden)]
+    fn allocate(&mut self, layout: Layout) -> Option<NonNull<u8>>;
+
+    #[doc(hidden)]
+    unsafe fn deallocate(&mut self, ptr: NonNull<u8>);
+
+    /// Returns the size of the block holding `ptr`.
+    #[doc(hidden)]
+    fn block_size(&self, ptr: NonNull<u8>) -> usize;
+
+    #[doc(hidden)]
+    fn used(&self) -> usize;
+
+    #[doc(hidden)]
+    fn free(&self) -> usize;
+}
+
+impl Pools for () {
+    const REGION_SIZE: usize = 0;
+    const EMPTY: Self = ();
+
+    unsafe fn init(&mut self, start: usize, _end: usize) -> Option<usize> {
+        Some(start)
+    }
+
+    fn allocate(&mut self, _layout: Layout) -> Option<NonNull<u8>> {
+        None
+    }
+
+    unsafe fn deallocate(&mut self, _ptr: NonNull<u8>) {}
+
+    fn block_size(&self, _ptr: NonNull<u8>) -> usize {
+        0
+    }
+
+    fn used(&self) -> usize {
+        0
+    }
+
+    fn free(&self) -> usize {
+        0
+    }
+}
+
+impl<const SIZE: usize, const COUNT: usize, Next: Pools> Pool<SIZE, COUNT, Next> {
+    const ALIGN: usize = {
+        assert!(
+            SIZE >= size_of::<usize>() && SIZE.is_multiple_of(align_of::<usize>()),
+            "Pool block size must be a multiple of `size_of::<usize>()`"
+        );
+        1 << SIZE.trailing_zeros()
+    };
+
+    fn contains(&self, ptr: NonNull<u8>) -> bool {
+        (self.start..self.start + SIZE * COUNT).contains(&(ptr.as_ptr() as usize))
+    }
+}
+
+impl<const SIZE: usize, const COUNT: usize, Next: Pools> Pools for Pool<SIZE, COUNT, Next> {
+    const REGION_SIZE: usize = SIZE * COUNT + Self::ALIGN - 1 + Next::REGION_SIZE;
+
+    const EMPTY: Self = Pool {
+        free: None,
+        start: 0,
+        fresh: 0,
+        used: 0,
+        next: Next::EMPTY,
+    };
+
+    unsafe fn init(&mut self, start: usize, end: usize) -> Option<usize> {
+        let start = start.checked_next_multiple_of(Self::ALIGN)?;
+        let pool_end = start.checked_add(SIZE * COUNT)?;
+        if pool_end > end {
+            return None;
+        }
+        self.start = start;
+        self.fresh = COUNT;
+        self.next.init(pool_end, end)
+    }
+
+    fn allocate(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        if layout.size() <= SIZE && layout.align() <= Self::ALIGN {
+            if let Some(block) = self.free {
+                // Safety: Free blocks hold a valid header.
+                self.free = unsafe { block.as_ref().next };
+                self.used += 1;
+                return Some(block.cast());
+            }
+            if self.fresh > 0 {
+                let block = self.start + (COUNT - self.fresh) * SIZE;
+                self.fresh -= 1;
+                self.used += 1;
+                // Safety: Blocks lie inside the pool, which does not start at null.
+                return Some(unsafe { NonNull::new_unchecked(block as *mut u8) });
+            }
+        }
+        self.next.allocate(layout)
+    }
+
+    unsafe fn deallocate(&mut self, ptr: NonNull<u8>) {
+        if !self.contains(ptr) {
+            return self.next.deallocate(ptr);
+        }
+        let block = ptr.cast::<FreeBlock>();
+        block.as_ptr().write(FreeBlock { next: self.free });
+        self.free = Some(block);
+        self.used -= 1;
+    }
+
+    fn block_size(&self, ptr: NonNull<u8>) -> usize {
+        if self.contains(ptr) {
+            SIZE
+        } else {
+            self.next.block_size(ptr)
+        }
+    }
+
+    fn used(&self) -> usize {
+        self.used * SIZE + self.next.used()
+    }
+
+    fn free(&self) -> usize {
+        (COUNT - self.used) * SIZE + self.next.free()
+    }
+}
+
+struct Inner<P> {
+    pools: P,
+    initialized: bool,
+}
+
+// Safety: The whole inner type is wrapped by a [Mutex].
+unsafe impl<P> Sync for Inner<P> {}
+unsafe impl<P> Send for Inner<P> {}
+
+/// A heap of fixed-size block pools.
+///
+/// Every allocation takes a block from the first pool whose blocks are large and
+/// aligned enough and that has a free block left, so both allocation and deallocation
+/// take constant time. See [`Pool`] for how to configure the pools.
+pub struct Heap<P: Pools> {
+    heap: Mutex<RefCell<Inner<P>>>,
+}
+
+impl<P: Pools> Heap<P> {
+    /// The number of bytes needed to initialize the heap, whatever the alignment of the
+    /// memory region.
+    pub const REGION_SIZE: usize = P::REGION_SIZE;
+
+    /// Create a new UNINITIALIZED heap allocator
+    ///
+    /// You must initialize this heap using the
+    /// [`init`](Self::init) method before using the allocator.
+    pub const fn empty() -> Heap<P> {
+        Heap {
+            heap: Mutex::new(RefCell::new(Inner {
+                pools: P::EMPTY,
+                initialized: false,
+            })),
+        }
+    }
+
+    /// Initializes the heap
+    ///
+    /// This function must be called BEFORE you run any code that makes use of the
+    /// allocator.
+    ///
+    /// `start_addr` is the address where the heap will be located.
+    ///
+    /// `size` is the size of the heap in bytes.
+    ///
+    /// The pools are carved out of the region one after another, each one aligned to
+    /// its block alignment. Memory left over after the last pool is not used.
+    ///
+    /// # Safety
+    ///
+    /// This function is safe if the following invariants hold:
+    ///
+    /// - `start_addr` points to valid memory.
+    /// - `size` is correct.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if either of the following are true:
+    ///
+    /// - this function is called more than ONCE.
+    /// - `size == 0`.
+    /// - the pools don't fit into the region. [`REGION_SIZE`](Self::REGION_SIZE) bytes
+    ///   are always enough.
+    pub unsafe fn init(&self, start_addr: usize, size: usize) {
+        if let Err(error) = self.try_init(start_addr, size) {
+            error.panic();
+        }
+    }
+
+    /// Initializes the heap like [`init`](Self::init), but returns an error instead of
+    /// panicking.
+    ///
+    /// # Safety
+    ///
+    /// This function is safe if the following invariants hold:
+    ///
+    /// - `start_addr` points to valid memory.
+    /// - `size` is correct.
+    ///
+    /// If the pools don't fit, the error is [`InitError::TooSmallAfterAlignment`] with
+    /// the whole region as `usable`.
+    pub unsafe fn try_init(&self, start_addr: usize, size: usize) -> Result<(), InitError> {
+        if size == 0 {
+            return Err(InitError::ZeroSize);
+        }
+        critical_section::with(|cs| {
+            let mut heap = self.heap.borrow_ref_mut(cs);
+            if heap.initialized {
+                return Err(InitError::AlreadyInitialized);
+            }
+            let end = start_addr.saturating_add(size);
+            if heap.pools.init(start_addr, end).is_none() {
+                return Err(InitError::TooSmallAfterAlignment { usable: size });
+            }
+            heap.initialized = true;
+            Ok(())
+        })
+    }
+
+    /// Returns the number of bytes in blocks that are in use.
+    pub fn used(&self) -> usize {
+        critical_section::with(|cs| self.heap.borrow_ref_mut(cs).pools.used())
+    }
+
+    /// Returns the number of bytes in blocks that are free.
+    pub fn free(&self) -> usize {
+        critical_section::with(|cs| {
+            let heap = self.heap.borrow_ref_mut(cs);
+            if !heap.initialized {
+                return 0;
+            }
+            heap.pools.free()
+        })
+    }
+
+    fn alloc(&self, layout: Layout) -> Option<NonNull<u8>> {
+        critical_section::with(|cs| self.heap.borrow_ref_mut(cs).pools.allocate(layout))
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8) {
+        critical_section::with(|cs| {
+            self.heap
+                .borrow_ref_mut(cs)
+                .pools
+                .deallocate(NonNull::new_unchecked(ptr))
+        });
+    }
+
+    /// Resizes the allocation at `ptr` to `new_size` bytes, keeping its alignment.
+    ///
+    /// The allocation stays in place if it still fits into its block. Otherwise a new
+    /// block is allocated, the contents are copied over and the old block is freed.
+    /// On failure the old block is left untouched.
+    unsafe fn realloc(
+        &self,
+        ptr: NonNull<u8>,
+        layout: Layout,
+        new_size: usize,
+    ) -> Option<NonNull<u8>> {
+        let new_layout = Layout::from_size_align(new_size, layout.align()).ok()?;
+        let block_size =
+            critical_section::with(|cs| self.heap.borrow_ref_mut(cs).pools.block_size(ptr));
+        if new_size <= block_size {
+            return Some(ptr);
+        }
+
+        let allocation = self.alloc(new_layout)?;
+        ptr::copy_nonoverlapping(ptr.as_ptr(), allocation.as_ptr(), layout.size());
+        self.dealloc(ptr.as_ptr());
+        Some(allocation)
+    }
+}
+
+impl<P: Pools> crate::Heap for Heap<P> {
+    unsafe fn init(&self, start_addr: usize, size: usize) {
+        self.init(start_addr, size);
+    }
+
+    unsafe fn try_init(&self, start_addr: usize, size: usize) -> Result<(), InitError> {
+        self.try_init(start_addr, size)
+    }
+
+    fn used(&self) -> usize {
+        self.used()
+    }
+
+    fn free(&self) -> usize {
+        self.free()
+    }
+}
+
+unsafe impl<P: Pools> GlobalAlloc for Heap<P> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.alloc(layout)
+            .map_or(ptr::null_mut(), |allocation| allocation.as_ptr())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
+        self.dealloc(ptr);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        self.realloc(NonNull::new_unchecked(ptr), layout, new_size)
+            .map_or(ptr::null_mut(), |allocation| allocation.as_ptr())
+    }
+}
+
+#[cfg(feature = "allocator_api")]
+mod allocator_api {
+    use super::*;
+    use crate::allocator;
+    use core::alloc::{AllocError, Allocator};
+
+    unsafe impl<P: Pools> Allocator for Heap<P> {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            match layout.size() {
+                0 => Ok(NonNull::slice_from_raw_parts(layout.dangling_ptr(), 0)),
+                size => self.alloc(layout).map_or(Err(AllocError), |allocation| {
+                    Ok(NonNull::slice_from_raw_parts(allocation, size))
+                }),
+            }
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            if layout.size() != 0 {
+                self.dealloc(ptr.as_ptr());
+            }
+        }
+
+        unsafe fn grow(
+            &self,
+            ptr: NonNull<u8>,
+            old_layout: Layout,
+            new_layout: Layout,
+        ) -> Result<NonNull<[u8]>, AllocError> {
+            allocator::resize(self, ptr, old_layout, new_layout, |ptr, layout, size| {
+                self.realloc(ptr, layout, size)
+            })
+        }
+
+        unsafe fn grow_zeroed(
+            &self,
+            ptr: NonNull<u8>,
+            old_layout: Layout,
+            new_layout: Layout,
+        ) -> Result<NonNull<[u8]>, AllocError> {
+            allocator::resize_zeroed(self, ptr, old_layout, new_layout, |ptr, layout, size| {
+                self.realloc(ptr, layout, size)
+            })
+        }
+
+        unsafe fn shrink(
+            &self,
+            ptr: NonNull<u8>,
+            old_layout: Layout,
+            new_layout: Layout,
+        ) -> Result<NonNull<[u8]>, AllocError> {
+            allocator::resize(self, ptr, old_layout, new_layout, |ptr, layout, size| {
+                self.realloc(ptr, layout, size)
+            })
+        }
+    }
+}
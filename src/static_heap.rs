@@ -0,0 +1,146 @@
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::Heap;
+#[cfg(feature = "llff")]
+use crate::LlffHeapWith;
+#[cfg(any(feature = "llff", feature = "tlsf"))]
+use crate::RawMutex;
+#[cfg(feature = "tlsf")]
+use crate::TlsfHeapWith;
+
+/// The address recorded by a [`StaticHeap`] whose heap couldn't be initialized.
+const FAILED: usize = usize::MAX;
+
+/// The buffer of a [`StaticHeap`], aligned to a word.
+#[repr(C)]
+struct Memory<const N: usize>([usize; 0], [MaybeUninit<u8>; N]);
+
+/// A heap that owns an `N` byte buffer and initializes itself on first use.
+///
+/// The buffer is stored inline, so the heap can be used as the global allocator without
+/// calling `init` before anything allocates:
+///
+/// ```rust
+/// use embedded_alloc::StaticTlsfHeap;
+///
+/// #[global_allocator]
+/// static HEAP: StaticTlsfHeap<4096> = StaticTlsfHeap::new();
+/// ```
+///
+/// The heap is initialized in a critical section on the first allocation or the first
+/// call of [`heap`](Self::heap). It keeps pointers into the buffer from then on, so a
+/// `StaticHeap` is meant to be a `static`. The address of the buffer is recorded at
+/// initialization and checked on every call, so a heap that was moved after its first
+/// use fails to allocate instead of handing out memory it no longer owns. Allocations
+/// also fail if `N` is too small for the heap. As the global allocator, both end in
+/// `handle_alloc_error`.
+pub struct StaticHeap<H, const N: usize> {
+    heap: H,
+    /// The address of `memory` when the heap was initialized, 0 before and
+    /// [`FAILED`] if the initialization failed.
+    address: AtomicUsize,
+    memory: UnsafeCell<Memory<N>>,
+}
+
+// Safety: The buffer is only accessed through the heap after it was handed over.
+unsafe impl<H: Sync, const N: usize> Sync for StaticHeap<H, N> {}
+
+impl<H: Heap, const N: usize> StaticHeap<H, N> {
+    /// Creates a heap that gives its buffer to `heap`, which must not be initialized.
+    pub const fn with_heap(heap: H) -> Self {
+        const { assert!(N > 0, "The buffer of a StaticHeap can't be empty") };
+        StaticHeap {
+            heap,
+            address: AtomicUsize::new(0),
+            memory: UnsafeCell::new(Memory([], [MaybeUninit::uninit(); N])),
+        }
+    }
+
+    /// Returns the heap, initializing it first if necessary.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `N` is too small for the heap, or if the heap was
+    /// moved after its first use.
+    pub fn heap(&'static self) -> &'static H {
+        match self.initialized() {
+            Some(heap) => heap,
+            None => panic!("StaticHeap is too small for its heap or was moved after its first use"),
+        }
+    }
+
+    /// Returns the heap, or `None` if it can't be initialized or the buffer has moved.
+    ///
+    /// This doesn't panic, as it is called by the `GlobalAlloc` methods.
+    fn initialized(&self) -> Option<&H> {
+        let start = self.memory.get() as usize;
+        let mut address = self.address.load(Ordering::Acquire);
+        if address == 0 {
+            address = critical_section::with(|_| {
+                if self.address.load(Ordering::Relaxed) == 0 {
+                    // Safety: The buffer is given to the heap only once, here.
+                    let address = match unsafe { self.heap.try_init(start, N) } {
+                        Ok(()) => start,
+                        Err(_) => FAILED,
+                    };
+                    self.address.store(address, Ordering::Release);
+                }
+                self.address.load(Ordering::Relaxed)
+            });
+        }
+        (address == start).then_some(&self.heap)
+    }
+}
+
+#[cfg(feature = "llff")]
+impl<R: RawMutex, const N: usize> StaticHeap<LlffHeapWith<R>, N> {
+    /// Creates a linked list first fit heap with an `N` byte buffer.
+    pub const fn new() -> Self {
+        Self::with_heap(LlffHeapWith::empty())
+    }
+}
+
+#[cfg(feature = "llff")]
+impl<R: RawMutex, const N: usize> Default for StaticHeap<LlffHeapWith<R>, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "tlsf")]
+impl<R: RawMutex, const N: usize> StaticHeap<TlsfHeapWith<R>, N> {
+    /// Creates a two-level segregated fit heap with an `N` byte buffer.
+    pub const fn new() -> Self {
+        Self::with_heap(TlsfHeapWith::empty())
+    }
+}
+
+#[cfg(feature = "tlsf")]
+impl<R: RawMutex, const N: usize> Default for StaticHeap<TlsfHeapWith<R>, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<H: Heap, const N: usize> GlobalAlloc for StaticHeap<H, N> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.initialized()
+            .map_or(ptr::null_mut(), |heap| heap.alloc(layout))
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // A heap that was moved can't take the block back, so it is leaked.
+        if let Some(heap) = self.initialized() {
+            heap.dealloc(ptr, layout);
+        }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        self.initialized()
+            .map_or(ptr::null_mut(), |heap| heap.realloc(ptr, layout, new_size))
+    }
+}
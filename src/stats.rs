@@ -0,0 +1,75 @@
+use core::alloc::Layout;
+
+/// Allocation statistics of a heap
+///
+/// Byte counts are the sizes requested by the callers and don't include any overhead
+/// of the allocator itself. Use `Heap::used` and `Heap::free` for that. With the
+/// `isr_cache` feature, allocations that fit a size class of the cache are counted with
+/// the size of the class, and blocks in the cache count as allocated.
+///
+/// Reallocations count as one deallocation of the old size and one allocation of the
+/// new size, whether or not the memory block had to be moved.
+///
+/// The counters wrap around on overflow.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HeapStats {
+    /// Bytes currently allocated.
+    pub allocated_bytes: usize,
+    /// The largest value `allocated_bytes` has reached, the high-water mark.
+    pub peak_allocated_bytes: usize,
+    /// Number of successful allocations.
+    pub allocations: usize,
+    /// Number of deallocations.
+    pub deallocations: usize,
+    /// Number of allocations that failed because the heap had no suitable free block.
+    pub failed_allocations: usize,
+}
+
+impl HeapStats {
+    pub(crate) const fn new() -> HeapStats {
+        HeapStats {
+            allocated_bytes: 0,
+            peak_allocated_bytes: 0,
+            allocations: 0,
+            deallocations: 0,
+            failed_allocations: 0,
+        }
+    }
+
+    /// Returns the number of allocations that have not been freed yet.
+    pub fn live_allocations(&self) -> usize {
+        self.allocations.wrapping_sub(self.deallocations)
+    }
+
+    pub(crate) fn record_alloc(&mut self, layout: Layout, succeeded: bool) {
+        if succeeded {
+            self.allocations = self.allocations.wrapping_add(1);
+            self.allocated_bytes += size(layout);
+            self.peak_allocated_bytes = self.peak_allocated_bytes.max(self.allocated_bytes);
+        } else {
+            self.failed_allocations = self.failed_allocations.wrapping_add(1);
+        }
+    }
+
+    pub(crate) fn record_dealloc(&mut self, layout: Layout) {
+        self.deallocations = self.deallocations.wrapping_add(1);
+        self.allocated_bytes -= size(layout);
+    }
+
+    pub(crate) fn record_realloc(&mut self, layout: Layout, new_layout: Layout, succeeded: bool) {
+        if succeeded {
+            self.record_dealloc(layout);
+        }
+        self.record_alloc(new_layout, succeeded);
+    }
+}
+
+/// Returns the number of bytes counted for an allocation of `layout`.
+///
+/// With the interrupt cache, allocations are counted with the size of their block, as
+/// they can be taken from and returned to the cache without updating the statistics.
+fn size(layout: Layout) -> usize {
+    #[cfg(feature = "isr_cache")]
+    let layout = crate::cache::block_layout(layout);
+    layout.size()
+}
@@ -1,49 +1,319 @@
 use core::alloc::{GlobalAlloc, Layout};
 use core::cell::RefCell;
+use core::mem::{align_of, size_of, MaybeUninit};
+#[cfg(feature = "poison")]
+use core::ops::Range;
 use core::ptr::{self, NonNull};
 
 use const_default::ConstDefault;
-use critical_section::Mutex;
-use rlsf::Tlsf;
+use rlsf::{BlockInfo, Tlsf, GRANULARITY};
+
+#[cfg(feature = "isr_cache")]
+use crate::cache::{self, Cache};
+#[cfg(feature = "guard")]
+use crate::guard::{self, Guard};
+#[cfg(feature = "poison")]
+use crate::poison;
+#[cfg(feature = "guard")]
+use crate::GuardHandler;
+use crate::{
+    CorruptionKind, CriticalSectionRawMutex, Fragmentation, HeapCorruption, InitError, Introspect,
+    RawMutex, ResetError, TryAllocError,
+};
+#[cfg(feature = "stats")]
+use crate::{HeapStats, OomAction, OomHandler, Stats};
 
 type TlsfHeap = Tlsf<'static, usize, usize, { usize::BITS as usize }, { usize::BITS as usize }>;
 
+/// Flag in the size word of a TLSF block header marking the block as used.
+const BLOCK_USED: usize = 1;
+/// Flag in the size word of a TLSF block header marking the last block of a region.
+const BLOCK_SENTINEL: usize = 2;
+
 struct Inner {
     tlsf: TlsfHeap,
     initialized: bool,
     raw_block: Option<NonNull<[u8]>>,
     raw_block_size: usize,
+    regions: Option<NonNull<Region>>,
+    live: usize,
+    #[cfg(feature = "stats")]
+    stats: HeapStats,
+    #[cfg(feature = "stats")]
+    oom_handler: Option<OomHandler>,
+    #[cfg(feature = "guard")]
+    guard: Guard,
+}
+
+/// Returns the smallest free block, header included, that `Tlsf::allocate` takes for
+/// `layout`.
+///
+/// The TLSF pads the request for the block header and the alignment, and only searches
+/// the free lists whose blocks are all large enough. Every power of two range of sizes
+/// is split into `usize::BITS` lists, so the request is rounded up to the smallest size
+/// of its list.
+fn min_block_size(layout: Layout) -> Option<usize> {
+    let overhead = layout.align().saturating_sub(GRANULARITY / 2) + GRANULARITY / 2;
+    let size = layout
+        .size()
+        .checked_add(overhead)?
+        .checked_next_multiple_of(GRANULARITY)?;
+    let list_bits = size.ilog2().saturating_sub(usize::BITS.ilog2());
+    size.checked_next_multiple_of(1 << list_bits)
 }
 
-// Safety: The whole inner type is wrapped by a [Mutex].
+/// Header stored at the start of every region added with [`Heap::add_region`].
+struct Region {
+    block: NonNull<[u8]>,
+    size: usize,
+    next: Option<NonNull<Region>>,
+}
+
+impl Inner {
+    fn allocate(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        #[cfg(feature = "isr_cache")]
+        let layout = cache::block_layout(layout);
+        #[cfg(feature = "guard")]
+        let (layout, data_layout) = (self.guard.outer_layout(layout)?, layout);
+        let allocation = self.tlsf.allocate(layout)?;
+        #[cfg(feature = "poison")]
+        // Safety: The block was just allocated and is free memory apart from that.
+        unsafe {
+            // Up to the next granule, the block may still hold the header of the free
+            // block it was taken from.
+            let data = allocation.as_ptr();
+            let header =
+                ((data as usize).next_multiple_of(GRANULARITY) - data as usize).min(layout.size());
+            poison::check_freed(data.add(header), layout.size() - header);
+            poison::fill(data, layout.size(), poison::ALLOCATED);
+        }
+        #[cfg(feature = "guard")]
+        // Safety: The block was just allocated with the outer layout.
+        let allocation = unsafe { self.guard.insert(allocation, data_layout) };
+        self.live += 1;
+        Some(allocation)
+    }
+
+    /// Return whether `allocate` would succeed for `layout`, without allocating.
+    fn can_allocate(&self, layout: Layout) -> bool {
+        #[cfg(feature = "isr_cache")]
+        let layout = cache::block_layout(layout);
+        #[cfg(feature = "guard")]
+        let Some(layout) = self.guard.outer_layout(layout) else {
+            return false;
+        };
+        let Some(block_size) = min_block_size(layout) else {
+            return false;
+        };
+        self.free_blocks()
+            .any(|block_info| block_info.size() >= block_size)
+    }
+
+    /// Free the allocation at `ptr`, returning `false` if its canaries were overwritten.
+    unsafe fn deallocate(&mut self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        #[cfg(feature = "isr_cache")]
+        let layout = cache::block_layout(layout);
+        #[cfg(feature = "guard")]
+        let (ptr, layout) = match self.guard.remove(ptr, layout) {
+            Some(block) => (block, self.guard.outer_layout(layout).unwrap_unchecked()),
+            None => return false,
+        };
+        #[cfg(feature = "poison")]
+        let (block, prev_free, next_free) = self
+            .neighbourhood(ptr)
+            .expect("Freed memory is not part of the heap");
+        #[cfg(feature = "poison")]
+        poison::fill(
+            ptr.as_ptr(),
+            block.end - ptr.as_ptr() as usize,
+            poison::FREED,
+        );
+        self.tlsf.deallocate(ptr, layout.align());
+        #[cfg(feature = "poison")]
+        {
+            // The TLSF merged the block with its free neighbours, which leaves their
+            // headers inside the merged block. Headers fit into the first granule.
+            let start = if prev_free {
+                block.start
+            } else {
+                block.start + GRANULARITY
+            };
+            if start < ptr.as_ptr() as usize {
+                let len = ptr.as_ptr() as usize - start;
+                poison::fill(start as *mut u8, len, poison::FREED);
+            }
+            if next_free {
+                poison::fill(block.end as *mut u8, GRANULARITY, poison::FREED);
+            }
+        }
+        self.live -= 1;
+        true
+    }
+
+    /// Inserts the region at `start_addr` as the first free block of the heap.
+    unsafe fn init(&mut self, start_addr: usize, size: usize) -> Result<(), InitError> {
+        if self.initialized {
+            return Err(InitError::AlreadyInitialized);
+        }
+        if size == 0 {
+            return Err(InitError::ZeroSize);
+        }
+        #[cfg(feature = "poison")]
+        poison::fill(start_addr as *mut u8, size, poison::FREED);
+        let start = NonNull::new_unchecked(start_addr as *mut u8);
+        let block = NonNull::slice_from_raw_parts(start, size);
+        let Some(actual_size) = self.tlsf.insert_free_block_ptr(block) else {
+            let usable = start_addr
+                .checked_next_multiple_of(GRANULARITY)
+                .map_or(0, |aligned| {
+                    size.saturating_sub(aligned - start_addr) & !(GRANULARITY - 1)
+                });
+            return Err(InitError::TooSmallAfterAlignment { usable });
+        };
+        self.initialized = true;
+        self.raw_block = Some(NonNull::slice_from_raw_parts(start, actual_size.get()));
+        self.raw_block_size = size;
+        Ok(())
+    }
+
+    /// Puts the block given to `init` back into a fresh TLSF.
+    unsafe fn reset(&mut self) {
+        let Some(block) = self.raw_block else {
+            return;
+        };
+        let start = block.cast::<u8>();
+        #[cfg(feature = "poison")]
+        poison::fill(start.as_ptr(), self.raw_block_size, poison::FREED);
+        self.tlsf = ConstDefault::DEFAULT;
+        self.tlsf
+            .insert_free_block_ptr(NonNull::slice_from_raw_parts(start, self.raw_block_size));
+        self.regions = None;
+        self.live = 0;
+        #[cfg(feature = "stats")]
+        {
+            self.stats = HeapStats::new();
+        }
+        #[cfg(feature = "guard")]
+        self.guard.reset();
+    }
+
+    /// Returns the address range of the block holding the allocation at `ptr`, and
+    /// whether the blocks before and after it are free.
+    #[cfg(feature = "poison")]
+    fn neighbourhood(&self, ptr: NonNull<u8>) -> Option<(Range<usize>, bool, bool)> {
+        let addr = ptr.as_ptr() as usize;
+        for (block, _) in self.blocks() {
+            // Safety: We pass the memory blocks we previously gave to the TLSF
+            // to the `iter_blocks` method.
+            let mut blocks = unsafe { self.tlsf.iter_blocks(block) }.peekable();
+            let mut prev_free = false;
+            while let Some(block_info) = blocks.next() {
+                let start = block_info.as_ptr().cast::<u8>().as_ptr() as usize;
+                let range = start..start + block_info.size();
+                if range.contains(&addr) {
+                    let next_free = blocks.peek().is_some_and(|next| !next.is_occupied());
+                    return Some((range, prev_free, next_free));
+                }
+                prev_free = !block_info.is_occupied();
+            }
+        }
+        None
+    }
+
+    /// Returns the memory blocks the TLSF was given, with the size of the region each
+    /// one was carved from.
+    fn blocks(&self) -> impl Iterator<Item = (NonNull<[u8]>, usize)> + '_ {
+        let mut region = self.regions;
+        let raw_block = self.raw_block.map(|block| (block, self.raw_block_size));
+        raw_block.into_iter().chain(core::iter::from_fn(move || {
+            // Safety: Region headers live inside the heap memory and are never freed.
+            let current = unsafe { region?.as_ref() };
+            region = current.next;
+            Some((current.block, current.size))
+        }))
+    }
+
+    /// Returns the free blocks of all regions.
+    fn free_blocks(&self) -> impl Iterator<Item = BlockInfo<'_>> + '_ {
+        self.blocks()
+            .flat_map(|(block, _)| {
+                // Safety: We pass the memory blocks we previously gave to the TLSF
+                // to the `iter_blocks` method.
+                unsafe { self.tlsf.iter_blocks(block) }
+            })
+            .filter(|block_info| !block_info.is_occupied())
+    }
+
+    fn free(&self) -> usize {
+        if !self.initialized {
+            return 0;
+        }
+        self.free_blocks()
+            .map(|block_info| block_info.max_payload_size())
+            .sum()
+    }
+}
+
+// Safety: The whole inner type is only accessed while the heap is locked.
 unsafe impl Sync for Inner {}
 unsafe impl Send for Inner {}
 
 /// A two-Level segregated fit heap.
-pub struct Heap {
-    heap: Mutex<RefCell<Inner>>,
+///
+/// Unlike [`LlffHeap`](crate::LlffHeap), `realloc` copies moved data with the heap locked.
+pub struct Heap<R = CriticalSectionRawMutex> {
+    mutex: R,
+    heap: RefCell<Inner>,
+    #[cfg(feature = "isr_cache")]
+    cache: Cache,
 }
 
-impl Heap {
+// Safety: `heap` is only accessed while `mutex` is locked.
+unsafe impl<R: RawMutex + Sync> Sync for Heap<R> {}
+
+impl<R: RawMutex> Heap<R> {
     /// Create a new UNINITIALIZED heap allocator
     ///
     /// You must initialize this heap using the
     /// [`init`](Self::init) method before using the allocator.
-    pub const fn empty() -> Heap {
+    pub const fn empty() -> Heap<R> {
         Heap {
-            heap: Mutex::new(RefCell::new(Inner {
+            mutex: R::INIT,
+            heap: RefCell::new(Inner {
                 tlsf: ConstDefault::DEFAULT,
                 initialized: false,
                 raw_block: None,
                 raw_block_size: 0,
-            })),
+                regions: None,
+                live: 0,
+                #[cfg(feature = "stats")]
+                stats: HeapStats::new(),
+                #[cfg(feature = "stats")]
+                oom_handler: None,
+                #[cfg(feature = "guard")]
+                guard: Guard::new(),
+            }),
+            #[cfg(feature = "isr_cache")]
+            cache: Cache::new(),
         }
     }
 
+    /// Runs `f` on the heap state with the heap locked.
+    fn with<T>(&self, f: impl FnOnce(&mut Inner) -> T) -> T {
+        self.mutex.lock(|| f(&mut self.heap.borrow_mut()))
+    }
+
+    /// Runs `f` on the heap state, or returns `None` if the heap is locked already.
+    fn try_with<T>(&self, f: impl FnOnce(&mut Inner) -> T) -> Option<T> {
+        self.mutex
+            .try_lock(|| self.heap.try_borrow_mut().ok().map(|mut heap| f(&mut heap)))
+            .flatten()
+    }
+
     /// Initializes the heap
     ///
     /// This function must be called BEFORE you run any code that makes use of the
-    /// allocator.
+    /// allocator. A [`StaticHeap`](crate::StaticHeap) initializes itself instead.
     ///
     /// `start_addr` is the address where the heap will be located.
     ///
@@ -72,70 +342,555 @@ impl Heap {
     /// - this function is called more than ONCE.
     /// - `size`, after aligning start and end to `rlsf::GRANULARITY`, is smaller than `rlsf::GRANULARITY * 2`.
     pub unsafe fn init(&self, start_addr: usize, size: usize) {
+        if let Err(error) = self.with(|heap| heap.init(start_addr, size)) {
+            error.panic();
+        }
+    }
+
+    /// Initializes the heap like [`init`](Self::init), but returns an error instead of
+    /// panicking.
+    ///
+    /// Unlike `init`, which skips the bytes up to the next granule, this refuses a
+    /// `start_addr` that is not aligned to `align_of::<usize>()` with
+    /// [`InitError::Misaligned`], as it usually is a wrong address.
+    ///
+    /// # Safety
+    ///
+    /// This function is safe if the following invariants hold:
+    ///
+    /// - `start_addr` points to valid memory.
+    /// - `size` is correct.
+    pub unsafe fn try_init(&self, start_addr: usize, size: usize) -> Result<(), InitError> {
+        if !start_addr.is_multiple_of(align_of::<usize>()) {
+            return Err(InitError::Misaligned);
+        }
+        self.with(|heap| heap.init(start_addr, size))
+    }
+
+    /// Initializes the heap with `memory`
+    ///
+    /// This is the safe version of [`init`](Self::init): the exclusive `'static` borrow
+    /// guarantees that the memory is valid and not used for anything else.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic in the same cases as [`init`](Self::init).
+    pub fn init_with(&self, memory: &'static mut [MaybeUninit<u8>]) {
+        // Safety: The memory is valid and borrowed by the heap forever.
+        unsafe { self.init(memory.as_mut_ptr() as usize, memory.len()) }
+    }
+
+    /// Adds another memory region to the heap
+    ///
+    /// This allows pooling several memory banks, which don't have to be contiguous,
+    /// into one heap.
+    ///
+    /// `start_addr` is the address where the region will be located.
+    ///
+    /// `size` is the size of the region in bytes.
+    ///
+    /// A few bytes at the start of the region are used to keep track of it, they count
+    /// towards [`used`](Self::used).
+    ///
+    /// # Safety
+    ///
+    /// This function is safe if the following invariants hold:
+    ///
+    /// - `start_addr` points to valid memory.
+    /// - `size` is correct.
+    /// - the region does not overlap with memory already given to the heap.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if either of the following are true:
+    ///
+    /// - the heap has not been initialized with [`init`](Self::init).
+    /// - `size == 0`.
+    /// - `size`, after removing the region header and aligning start and end to
+    ///   `rlsf::GRANULARITY`, is smaller than `rlsf::GRANULARITY * 2`.
+    pub unsafe fn add_region(&self, start_addr: usize, size: usize) {
         assert!(size > 0);
-        critical_section::with(|cs| {
-            let mut heap = self.heap.borrow_ref_mut(cs);
-            assert!(!heap.initialized);
-            let block: NonNull<[u8]> =
-                NonNull::slice_from_raw_parts(NonNull::new_unchecked(start_addr as *mut u8), size);
+        self.with(|heap| {
+            assert!(heap.initialized);
+            let header = start_addr.next_multiple_of(align_of::<Region>());
+            let block_start = header + size_of::<Region>();
+            let Some(block_size) = (start_addr + size).checked_sub(block_start) else {
+                panic!("Allocation too small for heap");
+            };
+            #[cfg(feature = "poison")]
+            poison::fill(block_start as *mut u8, block_size, poison::FREED);
+            let block: NonNull<[u8]> = NonNull::slice_from_raw_parts(
+                NonNull::new_unchecked(block_start as *mut u8),
+                block_size,
+            );
             let Some(actual_size) = heap.tlsf.insert_free_block_ptr(block) else {
                 panic!("Allocation too small for heap");
             };
             let block: NonNull<[u8]> = NonNull::slice_from_raw_parts(
-                NonNull::new_unchecked(start_addr as *mut u8),
+                NonNull::new_unchecked(block_start as *mut u8),
                 actual_size.get(),
             );
-            heap.initialized = true;
-            heap.raw_block = Some(block);
-            heap.raw_block_size = size;
+            let region = header as *mut Region;
+            region.write(Region {
+                block,
+                size,
+                next: heap.regions,
+            });
+            heap.regions = Some(NonNull::new_unchecked(region));
         });
     }
 
+    /// Set a function that is called when an allocation fails.
+    ///
+    /// The handler runs with the heap unlocked, so it may log the failure or
+    /// free memory, for example by dropping caches. If it returns [`OomAction::Retry`]
+    /// the allocation is attempted again, so it must eventually return
+    /// [`OomAction::Fail`] if no more memory can be freed.
+    #[cfg(feature = "stats")]
+    pub fn set_oom_handler(&self, handler: OomHandler) {
+        self.with(|heap| heap.oom_handler = Some(handler));
+    }
+
+    /// Set the number of canary words in front of and behind every allocation.
+    ///
+    /// The canaries are checked when an allocation is freed and by
+    /// [`verify_all`](Self::verify_all). Leading canaries also protect the header that
+    /// keeps track of the allocation. The default is one word on each side.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if any allocation is live.
+    #[cfg(feature = "guard")]
+    pub fn set_canaries(&self, leading: usize, trailing: usize) {
+        self.with(|heap| heap.guard.set_canaries(leading, trailing));
+    }
+
+    /// Set a function that is called when overwritten canaries are found.
+    ///
+    /// The handler runs with the heap unlocked. Without a handler the heap
+    /// panics. The damaged allocation is not returned to the heap.
+    #[cfg(feature = "guard")]
+    pub fn set_guard_handler(&self, handler: GuardHandler) {
+        self.with(|heap| heap.guard.handler = Some(handler));
+    }
+
+    /// Check the canaries of all live allocations.
+    ///
+    /// The first allocation with overwritten canaries is reported to the guard handler
+    /// and `false` is returned.
+    #[cfg(feature = "guard")]
+    pub fn verify_all(&self) -> bool {
+        let corrupted = self.with(|heap| heap.guard.find_corrupted());
+        match corrupted {
+            Some((address, layout)) => {
+                self.report_overwritten(address, layout);
+                false
+            }
+            None => true,
+        }
+    }
+
+    /// Call the guard handler for an allocation with overwritten canaries.
+    ///
+    /// Allocations that fit a cache class are reported with the layout of the class,
+    /// which is the one their canaries were placed for.
+    #[cfg(feature = "guard")]
+    fn report_overwritten(&self, address: usize, layout: Layout) {
+        #[cfg(feature = "isr_cache")]
+        let layout = cache::block_layout(layout);
+        let handler = self.with(|heap| heap.guard.handler);
+        guard::report(handler, address, layout);
+    }
+
+    #[cfg(not(feature = "guard"))]
+    fn report_overwritten(&self, _address: usize, _layout: Layout) {}
+
     fn alloc(&self, layout: Layout) -> Option<NonNull<u8>> {
-        critical_section::with(|cs| self.heap.borrow_ref_mut(cs).tlsf.allocate(layout))
+        loop {
+            let allocation = self.with(|heap| {
+                let allocation = heap.allocate(layout);
+                #[cfg(feature = "stats")]
+                heap.stats.record_alloc(layout, allocation.is_some());
+                allocation
+            });
+            if allocation.is_some() || !self.retry_after_oom(layout) {
+                return allocation;
+            }
+        }
+    }
+
+    /// Call the out-of-memory handler and return whether to retry the allocation.
+    #[cfg(feature = "stats")]
+    fn retry_after_oom(&self, layout: Layout) -> bool {
+        let (handler, stats) = self.with(|heap| (heap.oom_handler, heap.stats));
+        handler.is_some_and(|handler| handler(layout, &stats) == OomAction::Retry)
+    }
+
+    #[cfg(not(feature = "stats"))]
+    fn retry_after_oom(&self, _layout: Layout) -> bool {
+        false
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        critical_section::with(|cs| {
-            self.heap
-                .borrow_ref_mut(cs)
-                .tlsf
-                .deallocate(NonNull::new_unchecked(ptr), layout.align())
+        let intact = self.with(|heap| {
+            #[cfg(feature = "stats")]
+            heap.stats.record_dealloc(layout);
+            heap.deallocate(NonNull::new_unchecked(ptr), layout)
+        });
+        if !intact {
+            self.report_overwritten(ptr as usize, layout);
+        }
+    }
+
+    /// Resizes the allocation at `ptr` to `new_size` bytes, keeping its alignment.
+    ///
+    /// The block is grown or shrunk in place if the neighbouring memory allows it.
+    /// Otherwise a new block is allocated, the contents are copied over and the old
+    /// block is freed. On failure the old block is left untouched.
+    ///
+    /// `Tlsf::reallocate` copies with the heap locked, while LLFF copies with the heap
+    /// unlocked. rlsf only offers resizing and moving in one step, so interrupts are
+    /// held off for the duration of the copy.
+    ///
+    /// Guarded allocations are always moved, as their canaries sit behind the data, and
+    /// so are poisoned ones, to check the memory they grow into.
+    unsafe fn realloc(
+        &self,
+        ptr: NonNull<u8>,
+        layout: Layout,
+        new_size: usize,
+    ) -> Option<NonNull<u8>> {
+        let new_layout = Layout::from_size_align(new_size, layout.align()).ok()?;
+        if cfg!(any(feature = "guard", feature = "poison")) {
+            let allocation = self.alloc(new_layout)?;
+            ptr::copy_nonoverlapping(
+                ptr.as_ptr(),
+                allocation.as_ptr(),
+                layout.size().min(new_size),
+            );
+            self.dealloc(ptr.as_ptr(), layout);
+            return Some(allocation);
+        }
+        loop {
+            let allocation = self.with(|heap| {
+                #[cfg(feature = "isr_cache")]
+                let new_layout = cache::block_layout(new_layout);
+                let allocation = heap.tlsf.reallocate(ptr, new_layout);
+                #[cfg(feature = "stats")]
+                heap.stats
+                    .record_realloc(layout, new_layout, allocation.is_some());
+                allocation
+            });
+            if allocation.is_some() || !self.retry_after_oom(new_layout) {
+                return allocation;
+            }
+        }
+    }
+
+    /// Frees all allocations at once, returning the heap to its state right after
+    /// [`init`](Self::init).
+    ///
+    /// Regions added with [`add_region`](Self::add_region) are no longer used, the
+    /// statistics start over and the interrupt cache is emptied. The out-of-memory and
+    /// guard handlers and the canary configuration are kept. An uninitialized heap is
+    /// left as it is.
+    ///
+    /// # Safety
+    ///
+    /// No memory allocated from the heap before the reset may be used afterwards.
+    pub unsafe fn reset(&self) {
+        self.with(|heap| heap.reset());
+        #[cfg(feature = "isr_cache")]
+        self.cache.drain(|_, _| {});
+    }
+
+    /// Returns the heap to its state right after [`init`](Self::init), if no allocation
+    /// is live.
+    ///
+    /// This is the safe version of [`reset`](Self::reset): it fails with the number of
+    /// live allocations instead. Blocks held by the interrupt cache count as live, so
+    /// [`drain_cache`](Self::drain_cache) has to be called first.
+    pub fn try_reset(&self) -> Result<(), ResetError> {
+        self.with(|heap| {
+            if heap.live != 0 {
+                return Err(ResetError {
+                    live_allocations: heap.live,
+                });
+            }
+            // Safety: Without live allocations no memory of the heap is in use.
+            unsafe { heap.reset() };
+            Ok(())
         })
     }
 
     /// Get the amount of bytes used by the allocator.
     pub fn used(&self) -> usize {
-        critical_section::with(|cs| {
-            let free = self.free_with_cs(cs);
-            self.heap.borrow_ref_mut(cs).raw_block_size - free
+        self.with(|heap| {
+            let size: usize = heap.blocks().map(|(_, size)| size).sum();
+            size - heap.free()
         })
     }
 
     /// Get the amount of free bytes in the allocator.
     pub fn free(&self) -> usize {
-        critical_section::with(|cs| self.free_with_cs(cs))
+        self.with(|heap| heap.free())
     }
 
-    fn free_with_cs(&self, cs: critical_section::CriticalSection) -> usize {
-        let inner_mut = self.heap.borrow_ref_mut(cs);
-        if !inner_mut.initialized {
-            return 0;
-        }
-        // Safety: We pass the memory block we previously initialized the heap with
-        // to the `iter_blocks` method.
-        unsafe {
-            inner_mut
-                .tlsf
-                .iter_blocks(inner_mut.raw_block.unwrap())
-                .filter(|block_info| !block_info.is_occupied())
+    /// Get the size of the largest free block in bytes.
+    ///
+    /// The TLSF rounds allocation requests up to its size classes, so an allocation of
+    /// this size is not guaranteed to succeed. Use [`can_allocate`](Self::can_allocate)
+    /// for an exact answer.
+    pub fn largest_free_block(&self) -> usize {
+        self.with(|heap| {
+            heap.free_blocks()
                 .map(|block_info| block_info.max_payload_size())
-                .sum::<usize>()
+                .max()
+                .unwrap_or(0)
+        })
+    }
+
+    /// Check whether an allocation with `layout` would currently succeed.
+    ///
+    /// The answer is only valid until the next allocation or deallocation. This walks
+    /// the free blocks with the heap locked, but doesn't change the heap.
+    pub fn can_allocate(&self, layout: Layout) -> bool {
+        self.with(|heap| heap.can_allocate(layout))
+    }
+
+    /// Get how fragmented the free memory is.
+    ///
+    /// Block sizes are counted without their header, like in [`free`](Self::free).
+    pub fn fragmentation(&self) -> Fragmentation {
+        self.with(|heap| {
+            heap.free_blocks()
+                .map(|block_info| block_info.max_payload_size())
+                .collect()
+        })
+    }
+
+    /// Check the blocks of the heap for corruption.
+    ///
+    /// Every block of every region is checked for a valid size and for lying inside its
+    /// region, no two free blocks may be adjacent, and the blocks have to add up to the
+    /// size of the region. The first invalid block is reported.
+    ///
+    /// The TLSF finds the next block through the size of the current one, so the walk
+    /// stops at the first block with an invalid size. Block headers overwritten with
+    /// arbitrary values can still lead it astray before that, but it never leaves the
+    /// region.
+    ///
+    /// This walks all blocks with the heap locked.
+    pub fn check(&self) -> Result<(), HeapCorruption> {
+        self.with(|heap| {
+            for (block, _) in heap.blocks() {
+                let start = block.cast::<u8>().as_ptr() as usize;
+                let end = start + block.len();
+                let mut total = 0;
+                let mut prev_free = false;
+                // The headers are walked here instead of with `Tlsf::iter_blocks`, which
+                // steps past a block before its size can be checked against the region.
+                let mut address = start.next_multiple_of(GRANULARITY);
+                while address + GRANULARITY <= end {
+                    // Safety: `rlsf` starts every block header inside the region with
+                    // the size of the block and its flags in the low bits.
+                    let size_and_flags = unsafe { (address as *const usize).read() };
+                    if size_and_flags & BLOCK_SENTINEL != 0 {
+                        break;
+                    }
+                    let size = size_and_flags & !(GRANULARITY - 1);
+                    let corruption = |kind| Err(HeapCorruption { address, kind });
+                    if size == 0 {
+                        return corruption(CorruptionKind::InvalidSize);
+                    }
+                    if address
+                        .checked_add(size)
+                        .is_none_or(|block_end| block_end > end)
+                    {
+                        return corruption(CorruptionKind::OutOfBounds);
+                    }
+                    let free = size_and_flags & BLOCK_USED == 0;
+                    if free && prev_free {
+                        return corruption(CorruptionKind::NotMerged);
+                    }
+                    prev_free = free;
+                    total += size;
+                    address += size;
+                }
+                // Every region ends with a sentinel block, which is not counted.
+                if total + GRANULARITY != end - start.next_multiple_of(GRANULARITY) {
+                    return Err(HeapCorruption {
+                        address: start,
+                        kind: CorruptionKind::SizeMismatch,
+                    });
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Allocate memory for `layout` without waiting for the heap lock.
+    ///
+    /// If the heap is locked, for example by the code an interrupt handler preempted,
+    /// this fails with [`TryAllocError::Contended`] instead of waiting. A failed
+    /// allocation is [`TryAllocError::Exhausted`], the out-of-memory handler isn't called.
+    pub fn try_alloc(&self, layout: Layout) -> Result<NonNull<u8>, TryAllocError> {
+        self.try_with(|heap| {
+            let allocation = heap.allocate(layout);
+            #[cfg(feature = "stats")]
+            heap.stats.record_alloc(layout, allocation.is_some());
+            allocation
+        })
+        .ok_or(TryAllocError::Contended)?
+        .ok_or(TryAllocError::Exhausted)
+    }
+
+    /// Free memory without waiting for the heap lock.
+    ///
+    /// If the heap is locked this fails with [`TryAllocError::Contended`] and the memory
+    /// stays allocated, so it can be freed again later.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been allocated by this heap with `layout` and not freed since.
+    pub unsafe fn try_dealloc(
+        &self,
+        ptr: NonNull<u8>,
+        layout: Layout,
+    ) -> Result<(), TryAllocError> {
+        let intact = self
+            .try_with(|heap| {
+                #[cfg(feature = "stats")]
+                heap.stats.record_dealloc(layout);
+                heap.deallocate(ptr, layout)
+            })
+            .ok_or(TryAllocError::Contended)?;
+        if !intact {
+            self.report_overwritten(ptr.as_ptr() as usize, layout);
+        }
+        Ok(())
+    }
+
+    /// Allocate memory for `layout` from the interrupt cache, without locking the heap.
+    ///
+    /// Allocations of up to 128 bytes with an alignment of at most `align_of::<usize>()`
+    /// are served from a few cached blocks per size class. This returns `None` for larger
+    /// layouts and once the class runs out of blocks, until
+    /// [`refill_cache`](Self::refill_cache) is called. The memory can be freed with
+    /// [`dealloc_cached`](Self::dealloc_cached) or like any other allocation.
+    #[cfg(feature = "isr_cache")]
+    pub fn alloc_cached(&self, layout: Layout) -> Option<NonNull<u8>> {
+        self.cache.take(layout)
+    }
+
+    /// Free memory into the interrupt cache, without locking the heap.
+    ///
+    /// Returns `false` if `layout` has no size class or its slots are full. The memory
+    /// then stays allocated, so it can be freed again later.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been allocated by this heap with `layout` and not freed since.
+    #[cfg(feature = "isr_cache")]
+    pub unsafe fn dealloc_cached(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        self.cache.put(ptr, layout)
+    }
+
+    /// Fill the empty slots of the interrupt cache with blocks from the heap.
+    ///
+    /// Call this outside of interrupt handlers, for example from the idle loop.
+    #[cfg(feature = "isr_cache")]
+    pub fn refill_cache(&self) {
+        self.with(|heap| {
+            for layout in self.cache.vacancies() {
+                let Some(block) = heap.allocate(layout) else {
+                    return;
+                };
+                #[cfg(feature = "stats")]
+                heap.stats.record_alloc(layout, true);
+                if !self.cache.put(block, layout) {
+                    // An interrupt handler filled the slot in the meantime.
+                    // Safety: The block was just allocated with `layout`.
+                    unsafe { heap.deallocate(block, layout) };
+                    #[cfg(feature = "stats")]
+                    heap.stats.record_dealloc(layout);
+                    return;
+                }
+            }
+        })
+    }
+
+    /// Return the blocks of the interrupt cache to the heap.
+    #[cfg(feature = "isr_cache")]
+    pub fn drain_cache(&self) {
+        let overwritten = self.with(|heap| {
+            let mut overwritten = None;
+            self.cache.drain(|block, layout| {
+                #[cfg(feature = "stats")]
+                heap.stats.record_dealloc(layout);
+                // Safety: Cached blocks are allocated with the layout of their class.
+                if !unsafe { heap.deallocate(block, layout) } {
+                    overwritten = Some((block.as_ptr() as usize, layout));
+                }
+            });
+            overwritten
+        });
+        if let Some((address, layout)) = overwritten {
+            self.report_overwritten(address, layout);
         }
     }
+
+    /// Get the allocation statistics of the heap.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> HeapStats {
+        self.with(|heap| heap.stats)
+    }
+}
+
+impl<R: RawMutex> crate::Heap for Heap<R> {
+    unsafe fn init(&self, start_addr: usize, size: usize) {
+        self.init(start_addr, size);
+    }
+
+    unsafe fn try_init(&self, start_addr: usize, size: usize) -> Result<(), InitError> {
+        self.try_init(start_addr, size)
+    }
+
+    fn used(&self) -> usize {
+        self.used()
+    }
+
+    fn free(&self) -> usize {
+        self.free()
+    }
+}
+
+impl<R: RawMutex> Introspect for Heap<R> {
+    fn largest_free_block(&self) -> usize {
+        self.largest_free_block()
+    }
+
+    fn can_allocate(&self, layout: Layout) -> bool {
+        self.can_allocate(layout)
+    }
+
+    fn fragmentation(&self) -> Fragmentation {
+        self.fragmentation()
+    }
+
+    fn check(&self) -> Result<(), HeapCorruption> {
+        self.check()
+    }
+}
+
+#[cfg(feature = "stats")]
+impl<R: RawMutex> Stats for Heap<R> {
+    fn stats(&self) -> HeapStats {
+        self.stats()
+    }
 }
 
-unsafe impl GlobalAlloc for Heap {
+unsafe impl<R: RawMutex> GlobalAlloc for Heap<R> {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
         self.alloc(layout)
             .map_or(ptr::null_mut(), |allocation| allocation.as_ptr())
@@ -144,14 +899,20 @@ unsafe impl GlobalAlloc for Heap {
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
         self.dealloc(ptr, layout)
     }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        self.realloc(NonNull::new_unchecked(ptr), layout, new_size)
+            .map_or(ptr::null_mut(), |allocation| allocation.as_ptr())
+    }
 }
 
 #[cfg(feature = "allocator_api")]
 mod allocator_api {
     use super::*;
+    use crate::allocator;
     use core::alloc::{AllocError, Allocator};
 
-    unsafe impl Allocator for Heap {
+    unsafe impl<R: RawMutex> Allocator for Heap<R> {
         fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
             match layout.size() {
                 0 => Ok(NonNull::slice_from_raw_parts(layout.dangling_ptr(), 0)),
@@ -166,5 +927,38 @@ mod allocator_api {
                 self.dealloc(ptr.as_ptr(), layout);
             }
         }
+
+        unsafe fn grow(
+            &self,
+            ptr: NonNull<u8>,
+            old_layout: Layout,
+            new_layout: Layout,
+        ) -> Result<NonNull<[u8]>, AllocError> {
+            allocator::resize(self, ptr, old_layout, new_layout, |ptr, layout, size| {
+                self.realloc(ptr, layout, size)
+            })
+        }
+
+        unsafe fn grow_zeroed(
+            &self,
+            ptr: NonNull<u8>,
+            old_layout: Layout,
+            new_layout: Layout,
+        ) -> Result<NonNull<[u8]>, AllocError> {
+            allocator::resize_zeroed(self, ptr, old_layout, new_layout, |ptr, layout, size| {
+                self.realloc(ptr, layout, size)
+            })
+        }
+
+        unsafe fn shrink(
+            &self,
+            ptr: NonNull<u8>,
+            old_layout: Layout,
+            new_layout: Layout,
+        ) -> Result<NonNull<[u8]>, AllocError> {
+            allocator::resize(self, ptr, old_layout, new_layout, |ptr, layout, size| {
+                self.realloc(ptr, layout, size)
+            })
+        }
     }
 }
@@ -0,0 +1,216 @@
+//! Host tests for the binary buddy heap.
+
+#![cfg(feature = "buddy")]
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
+
+mod common;
+
+use common::{init, Memory, Rng, HEAP_SIZE};
+use core::alloc::{GlobalAlloc, Layout};
+use embedded_alloc::BuddyHeap;
+
+type Heap = BuddyHeap;
+
+/// The first 32 byte block holds the bitmap.
+const USABLE_SIZE: usize = HEAP_SIZE - 32;
+
+#[test]
+fn empty_heap_has_no_memory() {
+    let heap = Heap::empty();
+    assert_eq!(heap.used(), 0);
+    assert_eq!(heap.free(), 0);
+}
+
+#[test]
+#[should_panic]
+fn init_twice_panics() {
+    let mut memory = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    unsafe { heap.init(memory.addr(), HEAP_SIZE) }
+}
+
+#[test]
+#[should_panic(expected = "Allocation too small for heap")]
+fn init_too_small_panics() {
+    let mut memory = Memory::new();
+    let heap = Heap::empty();
+    unsafe { heap.init(memory.addr(), 32) }
+}
+
+#[test]
+fn try_init() {
+    use embedded_alloc::InitError;
+
+    let mut memory = Memory::new();
+    let heap = Heap::empty();
+    let addr = memory.addr();
+    unsafe {
+        assert_eq!(heap.try_init(addr, 0), Err(InitError::ZeroSize));
+        assert_eq!(
+            heap.try_init(addr, 63),
+            Err(InitError::TooSmallAfterAlignment { usable: 32 })
+        );
+        assert_eq!(heap.try_init(addr, HEAP_SIZE), Ok(()));
+        assert_eq!(
+            heap.try_init(addr, HEAP_SIZE),
+            Err(InitError::AlreadyInitialized)
+        );
+    }
+    assert_eq!(heap.free(), USABLE_SIZE);
+}
+
+#[test]
+fn blocks_are_naturally_aligned() {
+    let mut memory = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    assert_eq!(heap.free(), USABLE_SIZE);
+
+    let mut used = 0;
+    for (size, align, block) in [(1, 1, 32), (33, 1, 64), (100, 8, 128), (8, 256, 256)] {
+        let ptr = unsafe { heap.alloc(Layout::from_size_align(size, align).unwrap()) };
+        assert!(!ptr.is_null());
+        assert_eq!(ptr as usize % block, 0);
+        used += block;
+        assert_eq!(heap.used(), used);
+    }
+    assert_eq!(heap.free() + heap.used(), USABLE_SIZE);
+}
+
+#[test]
+fn minimum_order() {
+    let mut memory = Memory::new();
+    let heap = BuddyHeap::<6>::empty();
+    unsafe { heap.init(memory.addr(), HEAP_SIZE) }
+    assert_eq!(heap.free(), HEAP_SIZE - 64);
+
+    let ptr = unsafe { heap.alloc(Layout::from_size_align(1, 1).unwrap()) };
+    assert_eq!(ptr as usize % 64, 0);
+    assert_eq!(heap.used(), 64);
+}
+
+#[test]
+fn buddies_merge_on_free() {
+    let mut memory = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    let small = Layout::from_size_align(32, 8).unwrap();
+    let blocks: Vec<_> = (0..8).map(|_| unsafe { heap.alloc(small) }).collect();
+    assert!(blocks.iter().all(|ptr| !ptr.is_null()));
+
+    let large = Layout::from_size_align(2048, 8).unwrap();
+    let ptr = unsafe { heap.alloc(large) };
+    assert_eq!(ptr as usize, memory.addr() + 2048);
+    assert!(unsafe { heap.alloc(large) }.is_null());
+
+    unsafe { heap.dealloc(ptr, large) };
+    for ptr in blocks {
+        unsafe { heap.dealloc(ptr, small) };
+    }
+    assert_eq!(heap.used(), 0);
+    assert_eq!(unsafe { heap.alloc(large) }, ptr);
+    // The lower half never merges into one block, because the bitmap sits in its first block.
+    assert!(unsafe { heap.alloc(large) }.is_null());
+}
+
+#[test]
+fn exhaustion() {
+    let mut memory = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    let layout = Layout::from_size_align(32, 8).unwrap();
+    let blocks: Vec<_> = (0..USABLE_SIZE / 32)
+        .map(|_| unsafe { heap.alloc(layout) })
+        .collect();
+    assert!(blocks.iter().all(|ptr| !ptr.is_null()));
+    assert_eq!(heap.free(), 0);
+    assert!(unsafe { heap.alloc(layout) }.is_null());
+
+    unsafe { heap.dealloc(blocks[3], layout) };
+    assert_eq!(heap.free(), 32);
+    assert_eq!(unsafe { heap.alloc(layout) }, blocks[3]);
+}
+
+#[test]
+fn realloc() {
+    let mut memory = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    let layout = Layout::from_size_align(500, 8).unwrap();
+    unsafe {
+        let ptr = heap.alloc(layout);
+        ptr.write_bytes(0x42, 500);
+        assert_eq!(heap.realloc(ptr, layout, 512), ptr);
+
+        let shrunk = heap.realloc(ptr, layout, 40);
+        assert_eq!(shrunk, ptr);
+        assert_eq!(heap.used(), 64);
+
+        let layout = Layout::from_size_align(40, 8).unwrap();
+        let moved = heap.realloc(ptr, layout, 1000);
+        assert_ne!(moved, ptr);
+        assert!((0..40).all(|i| *moved.add(i) == 0x42));
+        assert_eq!(heap.used(), 1024);
+    }
+}
+
+#[test]
+fn mixed_alloc_free() {
+    let mut memory = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    let mut rng = Rng(0x1234_5678);
+    let mut allocations: Vec<(*mut u8, Layout, u8)> = Vec::new();
+
+    for round in 0..10_000 {
+        if allocations.is_empty() || rng.next(3) != 0 {
+            let layout = Layout::from_size_align(1 + rng.next(300), 1 << rng.next(7)).unwrap();
+            let ptr = unsafe { heap.alloc(layout) };
+            if !ptr.is_null() {
+                assert_eq!(ptr as usize % layout.align(), 0);
+                unsafe { ptr.write_bytes(round as u8, layout.size()) };
+                allocations.push((ptr, layout, round as u8));
+            }
+        } else {
+            let (ptr, layout, tag) = allocations.swap_remove(rng.next(allocations.len()));
+            assert!((0..layout.size()).all(|i| unsafe { *ptr.add(i) } == tag));
+            unsafe { heap.dealloc(ptr, layout) };
+        }
+        assert_eq!(heap.free() + heap.used(), USABLE_SIZE);
+    }
+
+    for (ptr, layout, _) in allocations {
+        unsafe { heap.dealloc(ptr, layout) };
+    }
+    assert_eq!(heap.free(), USABLE_SIZE);
+    let layout = Layout::from_size_align(2048, 8).unwrap();
+    assert!(!unsafe { heap.alloc(layout) }.is_null());
+}
+
+#[cfg(feature = "allocator_api")]
+mod allocator_api {
+    use super::*;
+    use core::alloc::Allocator;
+
+    #[test]
+    fn zero_size() {
+        let mut memory = Memory::new();
+        let heap = init(Heap::empty(), &mut memory);
+        let ptr = heap.allocate(Layout::new::<()>()).unwrap();
+        assert_eq!(ptr.len(), 0);
+        unsafe { heap.deallocate(ptr.cast(), Layout::new::<()>()) };
+        assert_eq!(heap.used(), 0);
+    }
+
+    #[test]
+    fn grow_and_shrink() {
+        let mut memory = Memory::new();
+        let heap = init(Heap::empty(), &mut memory);
+        let mut xs = Vec::new_in(&heap);
+        for i in 0..60u32 {
+            xs.push(i);
+        }
+        assert_eq!(heap.used(), 256);
+        xs.truncate(10);
+        xs.shrink_to_fit();
+        assert_eq!(heap.used(), 64);
+        assert_eq!(xs, (0..10).collect::<Vec<_>>());
+        drop(xs);
+        assert_eq!(heap.free(), USABLE_SIZE);
+    }
+}
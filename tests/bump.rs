@@ -0,0 +1,173 @@
+//! Host tests for the bump heap.
+
+#![cfg(feature = "bump")]
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
+
+mod common;
+
+use common::{init, Memory, HEAP_SIZE};
+use core::alloc::{GlobalAlloc, Layout};
+use embedded_alloc::BumpHeap as Heap;
+
+#[test]
+fn empty_heap_has_no_memory() {
+    let heap = Heap::empty();
+    assert_eq!(heap.used(), 0);
+    assert_eq!(heap.free(), 0);
+}
+
+#[test]
+#[should_panic]
+fn init_twice_panics() {
+    let mut memory = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    unsafe { heap.init(memory.addr(), HEAP_SIZE) }
+}
+
+#[test]
+#[should_panic]
+fn init_zero_size_panics() {
+    let heap = Heap::empty();
+    unsafe { heap.init(0x1000, 0) }
+}
+
+#[test]
+fn try_init() {
+    use embedded_alloc::InitError;
+
+    let mut memory = Memory::new();
+    let heap = Heap::empty();
+    let addr = memory.addr();
+    unsafe {
+        assert_eq!(heap.try_init(addr, 0), Err(InitError::ZeroSize));
+        assert_eq!(heap.try_init(addr, HEAP_SIZE), Ok(()));
+        assert_eq!(
+            heap.try_init(addr, HEAP_SIZE),
+            Err(InitError::AlreadyInitialized)
+        );
+    }
+    assert_eq!(heap.free(), HEAP_SIZE);
+}
+
+#[test]
+fn bump_and_reset() {
+    let mut memory = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    let byte = Layout::new::<u8>();
+    let word = Layout::new::<u64>();
+    unsafe {
+        let first = heap.alloc(byte);
+        assert_eq!(first as usize, memory.addr());
+        let second = heap.alloc(word);
+        assert_eq!(second as usize, memory.addr() + 8);
+        assert_eq!(heap.used(), 16);
+        assert_eq!(heap.free(), HEAP_SIZE - 16);
+
+        // Only the most recent allocation is given back.
+        heap.dealloc(first, byte);
+        assert_eq!(heap.used(), 16);
+        heap.dealloc(second, word);
+        assert_eq!(heap.used(), 8);
+
+        assert!(heap
+            .alloc(Layout::from_size_align(HEAP_SIZE, 1).unwrap())
+            .is_null());
+        heap.reset();
+    }
+    assert_eq!(heap.used(), 0);
+    assert_eq!(heap.free(), HEAP_SIZE);
+    let ptr = unsafe { heap.alloc(Layout::from_size_align(HEAP_SIZE, 1).unwrap()) };
+    assert_eq!(ptr as usize, memory.addr());
+}
+
+#[test]
+fn alignment() {
+    let mut memory = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    for align in [1, 2, 8, 32, 64, 16, 4] {
+        let layout = Layout::from_size_align(3, align).unwrap();
+        let ptr = unsafe { heap.alloc(layout) };
+        assert!(!ptr.is_null());
+        assert_eq!(ptr as usize % align, 0);
+    }
+}
+
+#[test]
+fn realloc() {
+    let mut memory = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    let layout = Layout::from_size_align(16, 8).unwrap();
+    unsafe {
+        let first = heap.alloc(layout);
+        first.write_bytes(0x42, 16);
+        let grown = heap.realloc(first, layout, 64);
+        assert_eq!(grown, first);
+        assert_eq!(heap.used(), 64);
+
+        let grown_layout = Layout::from_size_align(64, 8).unwrap();
+        let second = heap.alloc(layout);
+        let moved = heap.realloc(grown, grown_layout, 128);
+        assert_ne!(moved, grown);
+        assert!(moved > second);
+        assert!((0..16).all(|i| *moved.add(i) == 0x42));
+        assert_eq!(heap.used(), 64 + 16 + 128);
+    }
+}
+
+#[test]
+fn realloc_shrink_in_place() {
+    let mut memory = Memory::new();
+    let heap = Heap::empty();
+    unsafe { heap.init(memory.addr(), 256) };
+    let layout = Layout::from_size_align(128, 8).unwrap();
+    unsafe {
+        let first = heap.alloc(layout);
+        let second = heap.alloc(Layout::from_size_align(120, 8).unwrap());
+        assert!(!second.is_null());
+        assert_eq!(heap.free(), 8);
+
+        // Not the last allocation: shrinks in place but keeps its tail reserved.
+        let shrunk = heap.realloc(first, layout, 64);
+        assert_eq!(shrunk, first);
+        assert_eq!(heap.free(), 8);
+
+        // The last allocation gives its tail back.
+        let second_layout = Layout::from_size_align(120, 8).unwrap();
+        let shrunk = heap.realloc(second, second_layout, 40);
+        assert_eq!(shrunk, second);
+        assert_eq!(heap.free(), 88);
+    }
+}
+
+#[cfg(feature = "allocator_api")]
+mod allocator_api {
+    use super::*;
+    use core::alloc::Allocator;
+
+    #[test]
+    fn zero_size() {
+        let mut memory = Memory::new();
+        let heap = init(Heap::empty(), &mut memory);
+        let ptr = heap.allocate(Layout::new::<()>()).unwrap();
+        assert_eq!(ptr.len(), 0);
+        unsafe { heap.deallocate(ptr.cast(), Layout::new::<()>()) };
+        assert_eq!(heap.used(), 0);
+    }
+
+    #[test]
+    fn grow_and_shrink() {
+        let mut memory = Memory::new();
+        let heap = init(Heap::empty(), &mut memory);
+        let mut xs = Vec::new_in(&heap);
+        for i in 0..500u32 {
+            xs.push(i);
+        }
+        assert_eq!(heap.used(), 2048);
+        xs.truncate(10);
+        xs.shrink_to_fit();
+        assert_eq!(heap.used(), 40);
+        assert_eq!(xs, (0..10).collect::<Vec<_>>());
+        drop(xs);
+        assert_eq!(heap.used(), 0);
+    }
+}
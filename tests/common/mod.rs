@@ -0,0 +1,47 @@
+//! Helpers shared by the host tests.
+//!
+//! The tests run with the `std` implementation of `critical-section`:
+//!
+//! ```bash
+//! cargo test --target x86_64-unknown-linux-gnu --tests --all-features
+//! ```
+
+// Every test file uses a different part of this module.
+#![allow(dead_code)]
+
+use core::mem::MaybeUninit;
+use embedded_alloc::Heap;
+
+pub const HEAP_SIZE: usize = 4096;
+
+/// Heap memory, aligned to its size so buddy blocks are naturally aligned as well.
+#[repr(align(4096))]
+pub struct Memory([MaybeUninit<u8>; HEAP_SIZE]);
+
+impl Memory {
+    pub const fn new() -> Memory {
+        Memory([MaybeUninit::uninit(); HEAP_SIZE])
+    }
+
+    pub fn addr(&mut self) -> usize {
+        self.0.as_mut_ptr() as usize
+    }
+}
+
+/// Initializes `heap` with all of `memory`.
+pub fn init<H: Heap>(heap: H, memory: &mut Memory) -> H {
+    unsafe { heap.init(memory.addr(), HEAP_SIZE) }
+    heap
+}
+
+/// A small xorshift generator, so alloc/free sequences are reproducible.
+pub struct Rng(pub u32);
+
+impl Rng {
+    pub fn next(&mut self, bound: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0 as usize % bound
+    }
+}
@@ -0,0 +1,257 @@
+//! Host tests for the traits shared by the heaps.
+
+#![cfg(any(
+    feature = "llff",
+    feature = "tlsf",
+    feature = "pool",
+    feature = "bump",
+    feature = "buddy"
+))]
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
+
+mod common;
+
+use common::{Memory, HEAP_SIZE};
+use core::alloc::Layout;
+use embedded_alloc::Heap;
+
+/// Allocates and frees through the traits only.
+fn alloc_and_free(heap: &impl Heap) {
+    let mut memory = Memory::new();
+    unsafe { heap.init(memory.addr(), HEAP_SIZE) };
+    let free = heap.free();
+    assert!(free > 0);
+
+    let layout = Layout::from_size_align(100, 8).unwrap();
+    let ptr = unsafe { heap.alloc(layout) };
+    assert!(!ptr.is_null());
+    assert!(heap.used() >= 100);
+    assert!(heap.free() < free);
+    unsafe { heap.dealloc(ptr, layout) };
+    assert_eq!(heap.free(), free);
+}
+
+#[cfg(feature = "llff")]
+#[test]
+fn llff() {
+    alloc_and_free(&embedded_alloc::LlffHeap::empty());
+}
+
+#[cfg(feature = "tlsf")]
+#[test]
+fn tlsf() {
+    alloc_and_free(&embedded_alloc::TlsfHeap::empty());
+}
+
+#[cfg(feature = "pool")]
+#[test]
+fn pool() {
+    use embedded_alloc::{Pool, PoolHeap};
+    alloc_and_free(&PoolHeap::<Pool<128, 16>>::empty());
+}
+
+#[cfg(feature = "bump")]
+#[test]
+fn bump() {
+    alloc_and_free(&embedded_alloc::BumpHeap::empty());
+}
+
+#[cfg(feature = "buddy")]
+#[test]
+fn buddy() {
+    alloc_and_free(&embedded_alloc::BuddyHeap::<5>::empty());
+}
+
+#[cfg(all(feature = "llff", feature = "tlsf"))]
+#[test]
+fn introspect() {
+    use embedded_alloc::Introspect;
+
+    fn inspect(heap: &impl Introspect) {
+        let mut memory = Memory::new();
+        unsafe { heap.init(memory.addr(), HEAP_SIZE) };
+        assert_eq!(heap.check(), Ok(()));
+        assert!(heap.largest_free_block() > 0);
+        assert!(heap.can_allocate(Layout::new::<u64>()));
+        assert_eq!(heap.fragmentation().free_blocks, 1);
+    }
+
+    inspect(&embedded_alloc::LlffHeap::empty());
+    inspect(&embedded_alloc::TlsfHeap::empty());
+}
+
+#[cfg(all(feature = "llff", feature = "tlsf", feature = "stats"))]
+#[test]
+fn stats() {
+    use embedded_alloc::Stats;
+
+    fn count(heap: &impl Stats) {
+        let mut memory = Memory::new();
+        unsafe { heap.init(memory.addr(), HEAP_SIZE) };
+        let layout = Layout::new::<u64>();
+        let ptr = unsafe { heap.alloc(layout) };
+        assert_eq!(heap.stats().live_allocations(), 1);
+        unsafe { heap.dealloc(ptr, layout) };
+        assert_eq!(heap.stats().live_allocations(), 0);
+    }
+
+    count(&embedded_alloc::LlffHeap::empty());
+    count(&embedded_alloc::TlsfHeap::empty());
+}
+
+#[cfg(feature = "llff")]
+#[test]
+fn init_macro() {
+    static HEAP: embedded_alloc::LlffHeap = embedded_alloc::LlffHeap::empty();
+    unsafe {
+        embedded_alloc::init!(HEAP, 1024);
+    }
+    assert!(HEAP.free() > 0);
+}
+
+#[cfg(feature = "tlsf")]
+#[test]
+fn try_init_macro() {
+    static HEAP: embedded_alloc::TlsfHeap = embedded_alloc::TlsfHeap::empty();
+    unsafe {
+        assert_eq!(embedded_alloc::try_init!(HEAP, 1024), Ok(()));
+        assert_eq!(
+            embedded_alloc::try_init!(HEAP, 1024, align = 16),
+            Err(embedded_alloc::InitError::AlreadyInitialized)
+        );
+    }
+    assert!(HEAP.free() > 0);
+}
+
+#[cfg(feature = "bump")]
+#[test]
+fn init_macro_with_placement() {
+    use core::alloc::GlobalAlloc;
+    use embedded_alloc::BumpHeap;
+
+    static FIRST: BumpHeap = BumpHeap::empty();
+    static SECOND: BumpHeap = BumpHeap::empty();
+    unsafe {
+        embedded_alloc::init!(FIRST, 1000, align = 256);
+        embedded_alloc::init!(SECOND, 100, link_section = ".bss.second_heap", align = 64,);
+    }
+    assert_eq!(FIRST.free(), 1000);
+    assert_eq!(SECOND.free(), 100);
+    let first = unsafe { FIRST.alloc(Layout::new::<u8>()) };
+    let second = unsafe { SECOND.alloc(Layout::new::<u8>()) };
+    assert!((first as usize).is_multiple_of(256));
+    assert!((second as usize).is_multiple_of(64));
+}
+
+#[cfg(all(feature = "llff", feature = "tlsf"))]
+#[test]
+fn static_heap() {
+    use core::alloc::GlobalAlloc;
+    use embedded_alloc::{StaticLlffHeap, StaticTlsfHeap};
+
+    static LLFF: StaticLlffHeap<1024> = StaticLlffHeap::new();
+    static TLSF: StaticTlsfHeap<1024> = StaticTlsfHeap::new();
+
+    let layout = Layout::from_size_align(100, 8).unwrap();
+    for heap in [&LLFF as &dyn GlobalAlloc, &TLSF] {
+        let ptr = unsafe { heap.alloc(layout) };
+        assert!(!ptr.is_null());
+        unsafe { heap.dealloc(ptr, layout) };
+    }
+    assert_eq!(LLFF.heap().free(), 1024);
+    assert!(TLSF.heap().free() > 0);
+}
+
+#[cfg(feature = "llff")]
+#[test]
+fn static_heap_moved() {
+    use core::alloc::GlobalAlloc;
+
+    let heap = embedded_alloc::StaticLlffHeap::<256>::new();
+    let layout = Layout::new::<u64>();
+    let ptr = unsafe { heap.alloc(layout) };
+    assert!(!ptr.is_null());
+    let moved = Box::new(heap);
+    unsafe {
+        assert!(moved.alloc(layout).is_null());
+        assert!(moved.realloc(ptr, layout, 16).is_null());
+        moved.dealloc(ptr, layout);
+    }
+}
+
+#[cfg(feature = "tlsf")]
+#[test]
+fn static_heap_too_small() {
+    use core::alloc::GlobalAlloc;
+
+    static HEAP: embedded_alloc::StaticTlsfHeap<8> = embedded_alloc::StaticTlsfHeap::new();
+    let layout = Layout::new::<u8>();
+    assert!(unsafe { HEAP.alloc(layout) }.is_null());
+    assert!(unsafe { HEAP.alloc(layout) }.is_null());
+}
+
+#[cfg(feature = "bump")]
+#[test]
+fn static_heap_with_heap() {
+    use embedded_alloc::{BumpHeap, StaticHeap};
+
+    static HEAP: StaticHeap<BumpHeap, 256> = StaticHeap::with_heap(BumpHeap::empty());
+    assert_eq!(HEAP.heap().free(), 256);
+}
+
+#[cfg(feature = "tlsf")]
+#[test]
+fn local_heap() {
+    use core::mem::MaybeUninit;
+
+    let mut buffer = [MaybeUninit::uninit(); 1024];
+    let heap = embedded_alloc::LocalTlsfHeap::new(&mut buffer);
+    assert!(heap.heap().free() > 0);
+}
+
+#[cfg(all(feature = "llff", feature = "allocator_api"))]
+#[test]
+fn local_heap_allocator() {
+    use core::mem::MaybeUninit;
+
+    let mut buffer = [MaybeUninit::uninit(); 1024];
+    let heap = embedded_alloc::LocalLlffHeap::new(&mut buffer);
+    let mut xs = Vec::new_in(&heap);
+    xs.extend(0..16u32);
+    assert!(heap.heap().used() >= 64);
+    drop(xs);
+    assert_eq!(heap.heap().used(), 0);
+}
+
+// Linker symbols around a region in `.bss`, like the ones `memory.x` would define.
+#[cfg(target_os = "linux")]
+core::arch::global_asm!(
+    ".pushsection .bss",
+    ".balign 16",
+    ".globl test_heap_start",
+    "test_heap_start:",
+    ".skip 1024",
+    ".globl test_heap_end",
+    "test_heap_end:",
+    ".popsection",
+);
+
+#[cfg(all(feature = "llff", target_os = "linux"))]
+#[test]
+fn init_from_linker() {
+    static HEAP: embedded_alloc::LlffHeap = embedded_alloc::LlffHeap::empty();
+    unsafe {
+        embedded_alloc::init_from_linker!(HEAP, test_heap_start, test_heap_end);
+    }
+    assert_eq!(HEAP.free(), 1024);
+}
+
+#[cfg(all(feature = "llff", target_os = "linux"))]
+#[test]
+#[should_panic(expected = "is not above its start")]
+fn init_from_linker_with_swapped_symbols_panics() {
+    static HEAP: embedded_alloc::LlffHeap = embedded_alloc::LlffHeap::empty();
+    unsafe {
+        embedded_alloc::init_from_linker!(HEAP, test_heap_end, test_heap_start);
+    }
+}
@@ -0,0 +1,191 @@
+//! Host tests for the fixed-size block pool heap.
+
+#![cfg(feature = "pool")]
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
+
+mod common;
+
+use common::{init, Memory, Rng, HEAP_SIZE};
+use core::alloc::{GlobalAlloc, Layout};
+use embedded_alloc::{Pool, PoolHeap};
+
+type Heap = PoolHeap<Pool<64, 16, Pool<256, 8>>>;
+
+const POOLS_SIZE: usize = 64 * 16 + 256 * 8;
+
+#[test]
+fn empty_heap_has_no_memory() {
+    let heap = Heap::empty();
+    assert_eq!(heap.used(), 0);
+    assert_eq!(heap.free(), 0);
+}
+
+#[test]
+#[should_panic]
+fn init_twice_panics() {
+    let mut memory = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    unsafe { heap.init(memory.addr(), HEAP_SIZE) }
+}
+
+#[test]
+#[should_panic(expected = "Allocation too small for heap")]
+fn init_too_small_panics() {
+    let mut memory = Memory::new();
+    let heap = Heap::empty();
+    unsafe { heap.init(memory.addr(), POOLS_SIZE - 1) }
+}
+
+#[test]
+fn try_init() {
+    use embedded_alloc::InitError;
+
+    let mut memory = Memory::new();
+    let heap = Heap::empty();
+    let addr = memory.addr();
+    unsafe {
+        assert_eq!(heap.try_init(addr, 0), Err(InitError::ZeroSize));
+        assert_eq!(
+            heap.try_init(addr, POOLS_SIZE - 1),
+            Err(InitError::TooSmallAfterAlignment {
+                usable: POOLS_SIZE - 1
+            })
+        );
+        assert_eq!(heap.try_init(addr, POOLS_SIZE), Ok(()));
+        assert_eq!(
+            heap.try_init(addr, POOLS_SIZE),
+            Err(InitError::AlreadyInitialized)
+        );
+    }
+    assert_eq!(heap.free(), POOLS_SIZE);
+}
+
+#[test]
+fn region_size_covers_any_alignment() {
+    assert_eq!(Heap::REGION_SIZE, POOLS_SIZE + 63 + 255);
+    let mut memory = Memory::new();
+    let heap = Heap::empty();
+    unsafe { heap.init(memory.addr() + 1, Heap::REGION_SIZE) }
+    assert_eq!(heap.free(), POOLS_SIZE);
+}
+
+#[test]
+fn smallest_pool_first() {
+    let mut memory = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    assert_eq!(heap.free(), POOLS_SIZE);
+
+    let small = Layout::from_size_align(10, 1).unwrap();
+    let ptr = unsafe { heap.alloc(small) };
+    assert!(!ptr.is_null());
+    assert_eq!(ptr as usize % 64, 0);
+    assert_eq!(heap.used(), 64);
+
+    let large = Layout::from_size_align(100, 8).unwrap();
+    let ptr = unsafe { heap.alloc(large) };
+    assert_eq!(ptr as usize % 256, 0);
+    assert_eq!(heap.used(), 64 + 256);
+
+    let aligned = Layout::from_size_align(8, 128).unwrap();
+    let ptr = unsafe { heap.alloc(aligned) };
+    assert_eq!(ptr as usize % 128, 0);
+    assert_eq!(heap.used(), 64 + 2 * 256);
+    assert_eq!(heap.free() + heap.used(), POOLS_SIZE);
+
+    assert!(unsafe { heap.alloc(Layout::from_size_align(257, 8).unwrap()) }.is_null());
+    assert!(unsafe { heap.alloc(Layout::from_size_align(8, 512).unwrap()) }.is_null());
+}
+
+#[test]
+fn exhaustion_falls_back_to_larger_pools() {
+    let mut memory = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    let blocks: Vec<_> = (0..16 + 8).map(|_| unsafe { heap.alloc(layout) }).collect();
+    assert!(blocks.iter().all(|ptr| !ptr.is_null()));
+    assert_eq!(heap.free(), 0);
+    assert!(unsafe { heap.alloc(layout) }.is_null());
+
+    unsafe { heap.dealloc(blocks[3], layout) };
+    assert_eq!(heap.free(), 64);
+    assert_eq!(unsafe { heap.alloc(layout) }, blocks[3]);
+}
+
+#[test]
+fn realloc() {
+    let mut memory = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    let layout = Layout::from_size_align(16, 8).unwrap();
+    unsafe {
+        let ptr = heap.alloc(layout);
+        ptr.write_bytes(0x42, 16);
+        assert_eq!(heap.realloc(ptr, layout, 64), ptr);
+
+        let moved = heap.realloc(ptr, layout, 200);
+        assert_ne!(moved, ptr);
+        assert!((0..16).all(|i| *moved.add(i) == 0x42));
+        assert_eq!(heap.used(), 256);
+    }
+}
+
+#[test]
+fn mixed_alloc_free() {
+    let mut memory = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    let mut rng = Rng(0x1234_5678);
+    let mut allocations: Vec<(*mut u8, Layout, u8)> = Vec::new();
+
+    for round in 0..10_000 {
+        if allocations.is_empty() || rng.next(3) != 0 {
+            let layout = Layout::from_size_align(1 + rng.next(256), 1 << rng.next(7)).unwrap();
+            let ptr = unsafe { heap.alloc(layout) };
+            if !ptr.is_null() {
+                assert_eq!(ptr as usize % layout.align(), 0);
+                unsafe { ptr.write_bytes(round as u8, layout.size()) };
+                allocations.push((ptr, layout, round as u8));
+            }
+        } else {
+            let (ptr, layout, tag) = allocations.swap_remove(rng.next(allocations.len()));
+            assert!((0..layout.size()).all(|i| unsafe { *ptr.add(i) } == tag));
+            unsafe { heap.dealloc(ptr, layout) };
+        }
+        assert_eq!(heap.free() + heap.used(), POOLS_SIZE);
+    }
+
+    for (ptr, layout, _) in allocations {
+        unsafe { heap.dealloc(ptr, layout) };
+    }
+    assert_eq!(heap.free(), POOLS_SIZE);
+}
+
+#[cfg(feature = "allocator_api")]
+mod allocator_api {
+    use super::*;
+    use core::alloc::Allocator;
+
+    #[test]
+    fn zero_size() {
+        let mut memory = Memory::new();
+        let heap = init(Heap::empty(), &mut memory);
+        let ptr = heap.allocate(Layout::new::<()>()).unwrap();
+        assert_eq!(ptr.len(), 0);
+        unsafe { heap.deallocate(ptr.cast(), Layout::new::<()>()) };
+        assert_eq!(heap.used(), 0);
+    }
+
+    #[test]
+    fn grow_and_shrink() {
+        let mut memory = Memory::new();
+        let heap = init(Heap::empty(), &mut memory);
+        let mut xs = Vec::new_in(&heap);
+        for i in 0..60u32 {
+            xs.push(i);
+        }
+        assert_eq!(heap.used(), 256);
+        xs.truncate(10);
+        xs.shrink_to_fit();
+        assert_eq!(xs, (0..10).collect::<Vec<_>>());
+        drop(xs);
+        assert_eq!(heap.free(), POOLS_SIZE);
+    }
+}
@@ -0,0 +1,802 @@
+//! Host tests for the two-level segregated fit heap.
+
+#![cfg(feature = "tlsf")]
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
+
+mod common;
+
+use common::{init, Memory, Rng, HEAP_SIZE};
+use core::alloc::{GlobalAlloc, Layout};
+use core::mem::MaybeUninit;
+use embedded_alloc::TlsfHeap as Heap;
+
+#[test]
+fn empty_heap_has_no_memory() {
+    let heap = Heap::empty();
+    assert_eq!(heap.free(), 0);
+    assert_eq!(heap.used(), 0);
+    assert!(unsafe { heap.alloc(Layout::new::<u32>()) }.is_null());
+}
+
+#[test]
+#[should_panic]
+fn init_twice_panics() {
+    let mut memory = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    unsafe { heap.init(memory.addr(), HEAP_SIZE) }
+}
+
+#[test]
+#[should_panic]
+fn init_zero_size_panics() {
+    let mut memory = Memory::new();
+    unsafe { Heap::empty().init(memory.addr(), 0) }
+}
+
+#[test]
+#[should_panic]
+fn init_too_small_panics() {
+    let mut memory = Memory::new();
+    unsafe { Heap::empty().init(memory.addr() + 1, 16) }
+}
+
+#[test]
+fn try_init() {
+    use embedded_alloc::InitError;
+
+    let mut memory = Memory::new();
+    let heap = Heap::empty();
+    let addr = memory.addr();
+    unsafe {
+        assert_eq!(heap.try_init(addr, 0), Err(InitError::ZeroSize));
+        assert_eq!(
+            heap.try_init(addr + 1, HEAP_SIZE - 1),
+            Err(InitError::Misaligned)
+        );
+        assert_eq!(
+            heap.try_init(addr, 40),
+            Err(InitError::TooSmallAfterAlignment { usable: 32 })
+        );
+        assert_eq!(heap.try_init(addr, HEAP_SIZE), Ok(()));
+        assert_eq!(
+            heap.try_init(addr, HEAP_SIZE),
+            Err(InitError::AlreadyInitialized)
+        );
+    }
+    assert!(heap.free() > 0);
+}
+
+#[test]
+fn init_with() {
+    let memory = Box::leak(Box::new([MaybeUninit::uninit(); HEAP_SIZE]));
+    let heap = Heap::empty();
+    heap.init_with(memory);
+    assert_eq!(heap.free() + heap.used(), HEAP_SIZE);
+}
+
+#[test]
+fn free_and_used_add_up_to_size() {
+    let mut memory = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    let initial_free = heap.free();
+    assert!(initial_free > HEAP_SIZE - 256);
+    assert_eq!(heap.free() + heap.used(), HEAP_SIZE);
+
+    let layout = Layout::from_size_align(100, 4).unwrap();
+    let ptr = unsafe { heap.alloc(layout) };
+    assert!(!ptr.is_null());
+    assert!(heap.free() <= initial_free - 100);
+    assert_eq!(heap.free() + heap.used(), HEAP_SIZE);
+
+    unsafe { heap.dealloc(ptr, layout) };
+    assert_eq!(heap.free(), initial_free);
+}
+
+#[test]
+fn alignment() {
+    let mut memory = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    let initial_free = heap.free();
+    let mut allocations = Vec::new();
+    for align in [1, 2, 4, 8, 16, 32, 64, 128, 256] {
+        let layout = Layout::from_size_align(3, align).unwrap();
+        let ptr = unsafe { heap.alloc(layout) };
+        assert!(!ptr.is_null());
+        assert_eq!(ptr as usize % align, 0);
+        allocations.push((ptr, layout));
+    }
+    for (ptr, layout) in allocations {
+        unsafe { heap.dealloc(ptr, layout) };
+    }
+    assert_eq!(heap.free(), initial_free);
+}
+
+#[cfg(not(feature = "guard"))]
+#[test]
+fn exhaustion() {
+    let mut memory = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    let initial_free = heap.free();
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    let mut allocations = Vec::new();
+    loop {
+        let ptr = unsafe { heap.alloc(layout) };
+        if ptr.is_null() {
+            break;
+        }
+        allocations.push(ptr);
+    }
+    assert!(!allocations.is_empty());
+    assert!(heap.free() < 64);
+    assert_eq!(heap.free() + heap.used(), HEAP_SIZE);
+
+    for ptr in allocations {
+        unsafe { heap.dealloc(ptr, layout) };
+    }
+    assert_eq!(heap.free(), initial_free);
+    // All blocks were merged again.
+    let ptr = unsafe { heap.alloc(Layout::from_size_align(HEAP_SIZE / 2, 1).unwrap()) };
+    assert!(!ptr.is_null());
+}
+
+#[cfg(not(any(feature = "guard", feature = "poison")))]
+#[test]
+fn realloc_in_place() {
+    let mut memory = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    let initial_free = heap.free();
+    let layout = Layout::from_size_align(32, 8).unwrap();
+    unsafe {
+        let ptr = heap.alloc(layout);
+        ptr.write_bytes(0xAB, 32);
+
+        let grown = heap.realloc(ptr, layout, 1024);
+        assert_eq!(grown, ptr);
+
+        let layout = Layout::from_size_align(1024, 8).unwrap();
+        let shrunk = heap.realloc(grown, layout, 16);
+        assert_eq!(shrunk, ptr);
+        assert!((0..16).all(|i| *shrunk.add(i) == 0xAB));
+
+        heap.dealloc(shrunk, Layout::from_size_align(16, 8).unwrap());
+    }
+    assert_eq!(heap.free(), initial_free);
+}
+
+#[test]
+fn realloc_moves_when_blocked() {
+    let mut memory = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    let initial_free = heap.free();
+    let layout = Layout::from_size_align(32, 8).unwrap();
+    unsafe {
+        let ptr = heap.alloc(layout);
+        let blocker = heap.alloc(layout);
+        ptr.write_bytes(0xCD, 32);
+
+        let moved = heap.realloc(ptr, layout, 256);
+        assert_ne!(moved, ptr);
+        assert!((0..32).all(|i| *moved.add(i) == 0xCD));
+
+        heap.dealloc(moved, Layout::from_size_align(256, 8).unwrap());
+        heap.dealloc(blocker, layout);
+    }
+    assert_eq!(heap.free(), initial_free);
+}
+
+#[test]
+fn add_region() {
+    let mut memory = Memory::new();
+    let mut second = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    let initial_free = heap.free();
+    unsafe { heap.add_region(second.addr(), HEAP_SIZE) };
+    assert!(heap.free() > initial_free + HEAP_SIZE - 256);
+    assert_eq!(heap.free() + heap.used(), 2 * HEAP_SIZE);
+
+    let layout = Layout::from_size_align(HEAP_SIZE - 256, 8).unwrap();
+    let first = unsafe { heap.alloc(layout) };
+    let other = unsafe { heap.alloc(layout) };
+    assert!(!first.is_null() && !other.is_null());
+    assert_eq!(heap.free() + heap.used(), 2 * HEAP_SIZE);
+
+    unsafe {
+        heap.dealloc(first, layout);
+        heap.dealloc(other, layout);
+    }
+    assert_eq!(heap.free() + heap.used(), 2 * HEAP_SIZE);
+}
+
+#[test]
+fn largest_free_block() {
+    let mut memory = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    assert_eq!(heap.largest_free_block(), heap.free());
+
+    let layout = Layout::from_size_align(HEAP_SIZE / 8, 8).unwrap();
+    let blocks = [(); 4].map(|_| unsafe { heap.alloc(layout) });
+    unsafe {
+        heap.dealloc(blocks[0], layout);
+        heap.dealloc(blocks[2], layout);
+    }
+    let free = heap.free();
+    let largest = heap.largest_free_block();
+    assert!(largest > HEAP_SIZE / 4);
+    assert!(largest < free);
+    assert!(heap.can_allocate(layout));
+    assert!(!heap.can_allocate(Layout::from_size_align(largest + 1, 1).unwrap()));
+    assert_eq!(heap.free(), free);
+
+    unsafe {
+        heap.dealloc(blocks[1], layout);
+        heap.dealloc(blocks[3], layout);
+    }
+    assert_eq!(heap.largest_free_block(), heap.free());
+}
+
+#[test]
+fn can_allocate_matches_alloc() {
+    // The size classes only round requests beyond the granularity above 4 KiB on 64-bit
+    // targets, so this needs a larger heap.
+    const SIZE: usize = 16 * HEAP_SIZE;
+    let mut memory = vec![0u64; SIZE / 8];
+    let heap = Heap::empty();
+    unsafe { heap.init(memory.as_mut_ptr() as usize, SIZE) };
+
+    // Just below the largest free block, the size classes decide.
+    let largest = heap.largest_free_block();
+    for size in largest - 1024..=largest {
+        let layout = Layout::from_size_align(size, 8).unwrap();
+        let expected = heap.can_allocate(layout);
+        let ptr = unsafe { heap.alloc(layout) };
+        assert_eq!(expected, !ptr.is_null(), "{layout:?}");
+        if !ptr.is_null() {
+            unsafe { heap.dealloc(ptr, layout) };
+        }
+    }
+
+    let mut rng = Rng(0x9E37_79B9);
+    let mut allocations = Vec::new();
+    let mut failed = 0;
+    for _ in 0..2_000 {
+        let layout = Layout::from_size_align(1 + rng.next(SIZE / 2), 1 << rng.next(8)).unwrap();
+        let free = heap.free();
+        #[cfg(feature = "stats")]
+        let stats = heap.stats();
+        let expected = heap.can_allocate(layout);
+        assert_eq!(heap.free(), free);
+        #[cfg(feature = "stats")]
+        assert_eq!(heap.stats(), stats);
+        let ptr = unsafe { heap.alloc(layout) };
+        assert_eq!(expected, !ptr.is_null(), "{layout:?}");
+        if ptr.is_null() {
+            failed += 1;
+        } else {
+            allocations.push((ptr, layout));
+        }
+        if !allocations.is_empty() && (ptr.is_null() || allocations.len() > 4) {
+            let (ptr, layout) = allocations.swap_remove(rng.next(allocations.len()));
+            unsafe { heap.dealloc(ptr, layout) };
+        }
+    }
+    assert!(failed > 100 && failed < 1_900, "{failed}");
+}
+
+#[test]
+fn fragmentation() {
+    let mut memory = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    let fragmentation = heap.fragmentation();
+    assert_eq!(fragmentation.free_blocks, 1);
+    assert_eq!(fragmentation.total_free, heap.free());
+    assert_eq!(fragmentation.percent(), 0);
+
+    let layout = Layout::from_size_align(HEAP_SIZE / 16, 8).unwrap();
+    let blocks = [(); 8].map(|_| unsafe { heap.alloc(layout) });
+    for ptr in blocks.iter().step_by(2) {
+        unsafe { heap.dealloc(*ptr, layout) };
+    }
+    let fragmentation = heap.fragmentation();
+    assert_eq!(fragmentation.free_blocks, 5);
+    assert_eq!(fragmentation.total_free, heap.free());
+    assert!(fragmentation.percent() > 0);
+}
+
+#[cfg(not(feature = "guard"))]
+#[test]
+fn check() {
+    use embedded_alloc::{CorruptionKind, HeapCorruption};
+
+    let mut memory = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    assert_eq!(heap.check(), Ok(()));
+
+    let layout = Layout::from_size_align(HEAP_SIZE / 16, 8).unwrap();
+    let blocks = [(); 8].map(|_| unsafe { heap.alloc(layout) });
+    for ptr in blocks.iter().step_by(2) {
+        unsafe { heap.dealloc(*ptr, layout) };
+    }
+    assert_eq!(heap.check(), Ok(()));
+
+    // An allocation is preceded by the block header, which starts with the block size.
+    let header = unsafe { blocks[1].cast::<usize>().sub(2) };
+    let size = unsafe { header.read() };
+    unsafe { header.write(size & 0b11) };
+    assert_eq!(
+        heap.check(),
+        Err(HeapCorruption {
+            address: header as usize,
+            kind: CorruptionKind::InvalidSize,
+        })
+    );
+    unsafe { header.write(size) };
+    assert_eq!(heap.check(), Ok(()));
+
+    // Marking the block as free puts two free blocks next to each other.
+    unsafe { header.write(size & !1) };
+    assert_eq!(
+        heap.check(),
+        Err(HeapCorruption {
+            address: header as usize,
+            kind: CorruptionKind::NotMerged,
+        })
+    );
+    unsafe { header.write(size) };
+
+    // A size running past the end of the heap is reported instead of followed.
+    unsafe { header.write((2 * HEAP_SIZE) | (size & 0b11)) };
+    assert_eq!(
+        heap.check(),
+        Err(HeapCorruption {
+            address: header as usize,
+            kind: CorruptionKind::OutOfBounds,
+        })
+    );
+    unsafe { header.write(size) };
+}
+
+#[test]
+fn mixed_alloc_free() {
+    let mut memory = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    let initial_free = heap.free();
+    let mut rng = Rng(0x1234_5678);
+    let mut allocations: Vec<(*mut u8, Layout, u8)> = Vec::new();
+
+    for round in 0..10_000 {
+        if allocations.is_empty() || rng.next(3) != 0 {
+            let layout = Layout::from_size_align(1 + rng.next(200), 1 << rng.next(6)).unwrap();
+            let ptr = unsafe { heap.alloc(layout) };
+            if !ptr.is_null() {
+                assert_eq!(ptr as usize % layout.align(), 0);
+                unsafe { ptr.write_bytes(round as u8, layout.size()) };
+                allocations.push((ptr, layout, round as u8));
+            }
+        } else {
+            let (ptr, layout, tag) = allocations.swap_remove(rng.next(allocations.len()));
+            assert!((0..layout.size()).all(|i| unsafe { *ptr.add(i) } == tag));
+            unsafe { heap.dealloc(ptr, layout) };
+        }
+        assert_eq!(heap.free() + heap.used(), HEAP_SIZE);
+        assert_eq!(heap.check(), Ok(()));
+    }
+
+    for (ptr, layout, _) in allocations {
+        unsafe { heap.dealloc(ptr, layout) };
+    }
+    assert_eq!(heap.free(), initial_free);
+}
+
+#[test]
+fn custom_lock() {
+    use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use embedded_alloc::{RawMutex, TlsfHeapWith};
+
+    static LOCKS: AtomicUsize = AtomicUsize::new(0);
+
+    struct SpinLock(AtomicBool);
+
+    unsafe impl RawMutex for SpinLock {
+        const INIT: Self = SpinLock(AtomicBool::new(false));
+
+        fn lock<R>(&self, f: impl FnOnce() -> R) -> R {
+            while self.0.swap(true, Ordering::Acquire) {
+                core::hint::spin_loop();
+            }
+            LOCKS.fetch_add(1, Ordering::Relaxed);
+            let result = f();
+            self.0.store(false, Ordering::Release);
+            result
+        }
+
+        fn try_lock<R>(&self, f: impl FnOnce() -> R) -> Option<R> {
+            if self.0.swap(true, Ordering::Acquire) {
+                return None;
+            }
+            LOCKS.fetch_add(1, Ordering::Relaxed);
+            let result = f();
+            self.0.store(false, Ordering::Release);
+            Some(result)
+        }
+    }
+
+    let mut memory = Memory::new();
+    let heap = TlsfHeapWith::<SpinLock>::empty();
+    unsafe { heap.init(memory.addr(), HEAP_SIZE) };
+    let free = heap.free();
+    let layout = Layout::new::<[u32; 4]>();
+    let ptr = unsafe { heap.alloc(layout) };
+    assert!(!ptr.is_null());
+    unsafe { heap.dealloc(ptr, layout) };
+    assert_eq!(heap.free(), free);
+    assert!(LOCKS.load(Ordering::Relaxed) >= 4);
+}
+
+#[test]
+fn try_alloc() {
+    use embedded_alloc::{RawMutex, TlsfHeapWith, TryAllocError};
+
+    /// A lock that always looks taken to `try_lock`.
+    struct Taken;
+
+    unsafe impl RawMutex for Taken {
+        const INIT: Self = Taken;
+
+        fn lock<R>(&self, f: impl FnOnce() -> R) -> R {
+            f()
+        }
+
+        fn try_lock<R>(&self, _f: impl FnOnce() -> R) -> Option<R> {
+            None
+        }
+    }
+
+    let mut memory = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    let layout = Layout::new::<u64>();
+    let free = heap.free();
+    let ptr = heap.try_alloc(layout).unwrap();
+    unsafe { heap.try_dealloc(ptr, layout) }.unwrap();
+    assert_eq!(heap.free(), free);
+    let too_large = Layout::from_size_align(2 * HEAP_SIZE, 8).unwrap();
+    assert_eq!(heap.try_alloc(too_large), Err(TryAllocError::Exhausted));
+
+    let mut memory = Memory::new();
+    let heap = TlsfHeapWith::<Taken>::empty();
+    unsafe { heap.init(memory.addr(), HEAP_SIZE) };
+    assert_eq!(heap.try_alloc(layout), Err(TryAllocError::Contended));
+    let ptr = unsafe { heap.alloc(layout) };
+    let ptr = core::ptr::NonNull::new(ptr).unwrap();
+    assert_eq!(
+        unsafe { heap.try_dealloc(ptr, layout) },
+        Err(TryAllocError::Contended)
+    );
+}
+
+#[test]
+fn reset() {
+    use embedded_alloc::ResetError;
+
+    let mut memory = Memory::new();
+    let mut second = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    let free = heap.free();
+    unsafe { heap.add_region(second.addr(), HEAP_SIZE) };
+
+    let layout = Layout::new::<u64>();
+    let first = unsafe { heap.alloc(layout) };
+    let _other = unsafe { heap.alloc(layout) };
+    assert_eq!(
+        heap.try_reset(),
+        Err(ResetError {
+            live_allocations: 2
+        })
+    );
+    unsafe { heap.dealloc(first, layout) };
+    assert_eq!(
+        heap.try_reset(),
+        Err(ResetError {
+            live_allocations: 1
+        })
+    );
+    unsafe { heap.reset() };
+    assert_eq!(heap.free(), free);
+    assert_eq!(heap.check(), Ok(()));
+
+    let ptr = unsafe { heap.alloc(layout) };
+    assert!(!ptr.is_null());
+    unsafe { heap.dealloc(ptr, layout) };
+    assert_eq!(heap.try_reset(), Ok(()));
+    assert_eq!(heap.free(), free);
+}
+
+#[cfg(feature = "isr_cache")]
+#[test]
+fn isr_cache() {
+    let mut memory = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    let free = heap.free();
+    let layout = Layout::from_size_align(24, 4).unwrap();
+    assert_eq!(heap.alloc_cached(layout), None);
+
+    heap.refill_cache();
+    assert!(heap.free() < free);
+    let blocks: Vec<_> = (0..4).map(|_| heap.alloc_cached(layout).unwrap()).collect();
+    assert!(blocks
+        .iter()
+        .all(|block| (block.as_ptr() as usize).is_multiple_of(4)));
+    assert_eq!(heap.alloc_cached(layout), None);
+    let large = Layout::from_size_align(256, 4).unwrap();
+    assert_eq!(heap.alloc_cached(large), None);
+
+    unsafe {
+        blocks[0].as_ptr().write_bytes(0x42, layout.size());
+        assert!(heap.dealloc_cached(blocks[0], layout));
+        heap.dealloc(blocks[1].as_ptr(), layout);
+        assert_eq!(heap.alloc_cached(layout), Some(blocks[0]));
+
+        let ptr = core::ptr::NonNull::new(heap.alloc(layout)).unwrap();
+        assert!(heap.dealloc_cached(ptr, layout));
+        for block in [blocks[0], blocks[2], blocks[3]] {
+            heap.dealloc(block.as_ptr(), layout);
+        }
+    }
+    heap.drain_cache();
+    assert_eq!(heap.free(), free);
+}
+
+#[cfg(all(
+    feature = "stats",
+    not(any(feature = "guard", feature = "poison", feature = "isr_cache"))
+))]
+#[test]
+fn stats() {
+    let mut memory = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    let layout = Layout::from_size_align(100, 4).unwrap();
+    unsafe {
+        let first = heap.alloc(layout);
+        let second = heap.alloc(layout);
+        heap.dealloc(first, layout);
+        let second = heap.realloc(second, layout, 150);
+        assert!(heap
+            .alloc(Layout::from_size_align(2 * HEAP_SIZE, 4).unwrap())
+            .is_null());
+
+        let stats = heap.stats();
+        assert_eq!(stats.allocated_bytes, 150);
+        assert_eq!(stats.peak_allocated_bytes, 200);
+        assert_eq!(stats.allocations, 3);
+        assert_eq!(stats.deallocations, 2);
+        assert_eq!(stats.live_allocations(), 1);
+        assert_eq!(stats.failed_allocations, 1);
+
+        heap.dealloc(second, Layout::from_size_align(150, 4).unwrap());
+    }
+    assert_eq!(heap.stats().allocated_bytes, 0);
+    assert_eq!(heap.stats().live_allocations(), 0);
+}
+
+#[cfg(feature = "stats")]
+#[test]
+fn oom_handler() {
+    use core::ptr;
+    use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+    use embedded_alloc::{HeapStats, OomAction};
+
+    static HEAP: Heap = Heap::empty();
+    static mut MEMORY: Memory = Memory::new();
+    static CACHE: AtomicPtr<u8> = AtomicPtr::new(ptr::null_mut());
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+    const CACHE_LAYOUT: Layout = Layout::new::<[u8; HEAP_SIZE / 2]>();
+
+    fn drop_cache(layout: Layout, stats: &HeapStats) -> OomAction {
+        let calls = CALLS.fetch_add(1, Ordering::Relaxed) + 1;
+        assert_eq!(layout.size(), HEAP_SIZE / 4 * 3);
+        assert_eq!(stats.failed_allocations, calls);
+        let cache = CACHE.swap(ptr::null_mut(), Ordering::Relaxed);
+        if cache.is_null() {
+            return OomAction::Fail;
+        }
+        unsafe { HEAP.dealloc(cache, CACHE_LAYOUT) };
+        OomAction::Retry
+    }
+
+    unsafe { HEAP.init(&raw mut MEMORY as usize, HEAP_SIZE) }
+    HEAP.set_oom_handler(drop_cache);
+    CACHE.store(unsafe { HEAP.alloc(CACHE_LAYOUT) }, Ordering::Relaxed);
+
+    let layout = Layout::from_size_align(HEAP_SIZE / 4 * 3, 8).unwrap();
+    let ptr = unsafe { HEAP.alloc(layout) };
+    assert!(!ptr.is_null());
+    assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+
+    assert!(unsafe { HEAP.alloc(layout) }.is_null());
+    assert_eq!(CALLS.load(Ordering::Relaxed), 2);
+}
+
+#[cfg(all(feature = "guard", not(feature = "isr_cache")))]
+#[test]
+fn canaries() {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    static OVERWRITTEN: AtomicUsize = AtomicUsize::new(0);
+
+    fn record(address: usize, layout: Layout) {
+        assert_eq!(layout.size(), 10);
+        OVERWRITTEN.store(address, Ordering::Relaxed);
+    }
+
+    let mut memory = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    let initial_free = heap.free();
+    heap.set_canaries(2, 2);
+    heap.set_guard_handler(record);
+    let layout = Layout::from_size_align(10, 1).unwrap();
+    unsafe {
+        let first = heap.alloc(layout);
+        let second = heap.alloc(layout);
+        first.write_bytes(0, layout.size());
+        second.write_bytes(0, layout.size());
+        assert!(heap.verify_all());
+        heap.dealloc(first, layout);
+        assert_eq!(OVERWRITTEN.load(Ordering::Relaxed), 0);
+
+        second.add(layout.size()).write(0);
+        assert!(!heap.verify_all());
+        assert_eq!(OVERWRITTEN.load(Ordering::Relaxed), second as usize);
+        OVERWRITTEN.store(0, Ordering::Relaxed);
+        heap.dealloc(second, layout);
+        assert_eq!(OVERWRITTEN.load(Ordering::Relaxed), second as usize);
+    }
+    // The damaged block is not returned to the heap.
+    assert!(heap.free() < initial_free);
+    assert!(heap.verify_all());
+}
+
+/// With `isr_cache`, small allocations are guarded at the size of their class.
+#[cfg(all(feature = "guard", feature = "isr_cache"))]
+#[test]
+fn canaries_guard_the_cache_class() {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    static OVERWRITTEN: AtomicUsize = AtomicUsize::new(0);
+
+    fn record(address: usize, layout: Layout) {
+        assert_eq!(layout.size(), 16);
+        OVERWRITTEN.store(address, Ordering::Relaxed);
+    }
+
+    let mut memory = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    heap.set_canaries(2, 2);
+    heap.set_guard_handler(record);
+    let layout = Layout::from_size_align(10, 1).unwrap();
+    unsafe {
+        let ptr = heap.alloc(layout);
+        // An overrun within the class goes unnoticed.
+        ptr.write_bytes(0, 16);
+        assert!(heap.verify_all());
+
+        ptr.add(16).write(0);
+        assert!(!heap.verify_all());
+        assert_eq!(OVERWRITTEN.load(Ordering::Relaxed), ptr as usize);
+        OVERWRITTEN.store(0, Ordering::Relaxed);
+        heap.dealloc(ptr, layout);
+        assert_eq!(OVERWRITTEN.load(Ordering::Relaxed), ptr as usize);
+    }
+}
+
+#[cfg(feature = "guard")]
+#[test]
+#[should_panic(expected = "Heap canaries overwritten")]
+fn overwritten_canaries_panic_without_handler() {
+    let mut memory = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    let layout = Layout::from_size_align(10, 1).unwrap();
+    unsafe {
+        let ptr = heap.alloc(layout);
+        ptr.sub(1).write(0);
+        heap.dealloc(ptr, layout);
+    }
+}
+
+#[cfg(feature = "poison")]
+#[test]
+fn poison() {
+    let mut memory = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    unsafe {
+        let ptr = heap.alloc(layout);
+        assert!((0..64).all(|i| *ptr.add(i) == 0xA5));
+        let other = heap.alloc(layout);
+        heap.dealloc(ptr, layout);
+        // The start of the free block holds the free list header.
+        assert!((32..64).all(|i| *ptr.add(i) == 0xDD));
+        heap.dealloc(other, layout);
+    }
+}
+
+#[cfg(feature = "poison")]
+#[test]
+#[should_panic(expected = "was modified")]
+fn write_after_free_panics() {
+    let mut memory = Memory::new();
+    let heap = init(Heap::empty(), &mut memory);
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    unsafe {
+        let ptr = heap.alloc(layout);
+        heap.dealloc(ptr, layout);
+        ptr.add(48).write(0);
+        heap.alloc(layout);
+    }
+}
+
+#[cfg(feature = "allocator_api")]
+mod allocator_api {
+    use super::*;
+    use core::alloc::Allocator;
+
+    #[test]
+    fn zero_size() {
+        let mut memory = Memory::new();
+        let heap = init(Heap::empty(), &mut memory);
+        let initial_free = heap.free();
+        let layout = Layout::from_size_align(0, 16).unwrap();
+
+        let allocation = heap.allocate(layout).unwrap();
+        assert_eq!(allocation.len(), 0);
+        assert_eq!(allocation.cast::<u8>().as_ptr() as usize % 16, 0);
+        assert_eq!(heap.free(), initial_free);
+
+        let new_layout = Layout::from_size_align(8, 16).unwrap();
+        let grown = unsafe { heap.grow_zeroed(allocation.cast(), layout, new_layout) }.unwrap();
+        assert_eq!(unsafe { grown.as_ref() }, &[0; 8]);
+        assert!(heap.free() < initial_free);
+
+        let shrunk = unsafe { heap.shrink(grown.cast(), new_layout, layout) }.unwrap();
+        assert_eq!(shrunk.len(), 0);
+        assert_eq!(heap.free(), initial_free);
+        unsafe { heap.deallocate(shrunk.cast(), layout) };
+        assert_eq!(heap.free(), initial_free);
+    }
+
+    #[cfg(not(any(feature = "guard", feature = "poison")))]
+    #[test]
+    fn grow_and_shrink() {
+        let mut memory = Memory::new();
+        let heap = init(Heap::empty(), &mut memory);
+        let initial_free = heap.free();
+        let mut xs = Vec::new_in(&heap);
+        for i in 0..500u32 {
+            xs.push(i);
+        }
+        xs.truncate(10);
+        xs.shrink_to_fit();
+        assert_eq!(xs, (0..10).collect::<Vec<_>>());
+        drop(xs);
+        assert_eq!(heap.free(), initial_free);
+    }
+
+    #[test]
+    fn grow_with_larger_alignment() {
+        let mut memory = Memory::new();
+        let heap = init(Heap::empty(), &mut memory);
+        let initial_free = heap.free();
+        let old_layout = Layout::from_size_align(8, 1).unwrap();
+        let new_layout = Layout::from_size_align(64, 64).unwrap();
+        unsafe {
+            let allocation = heap.allocate(old_layout).unwrap().cast::<u8>();
+            allocation.as_ptr().write_bytes(0x11, 8);
+            let grown = heap
+                .grow_zeroed(allocation, old_layout, new_layout)
+                .unwrap();
+            assert_eq!(grown.cast::<u8>().as_ptr() as usize % 64, 0);
+            assert_eq!(&grown.as_ref()[..8], &[0x11; 8]);
+            assert_eq!(&grown.as_ref()[8..], &[0; 56]);
+            heap.deallocate(grown.cast(), new_layout);
+        }
+        assert_eq!(heap.free(), initial_free);
+    }
+}